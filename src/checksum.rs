@@ -0,0 +1,351 @@
+use crate::config::ChecksumAlgorithm;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee,
+    0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be,
+    0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa,
+    0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+    0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c,
+    0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05,
+    0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039,
+    0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1,
+    0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5,
+    0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3,
+    0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc,
+    0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+    0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+    0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3,
+    0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5,
+    0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208,
+    0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+enum State {
+    Md5([u32; 4]),
+    Sha1([u32; 5]),
+    Sha256([u32; 8]),
+}
+
+/// Incremental hasher for the 64-byte block digests supported by `--checksum`
+pub struct Hasher {
+    state: State,
+    buffer: [u8; 64],
+    buffer_len: usize,
+    total_len: u64,
+}
+
+impl Hasher {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        let state: State = match algorithm {
+            ChecksumAlgorithm::Md5 => State::Md5([0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476]),
+            ChecksumAlgorithm::Sha1 => State::Sha1([
+                0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0,
+            ]),
+            ChecksumAlgorithm::Sha256 => State::Sha256([
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c,
+                0x1f83d9ab, 0x5be0cd19,
+            ]),
+        };
+
+        Self {
+            state,
+            buffer: [0; 64],
+            buffer_len: 0,
+            total_len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len = self.total_len.wrapping_add(data.len() as u64);
+
+        while !data.is_empty() {
+            let take: usize = (64 - self.buffer_len).min(data.len());
+            self.buffer[self.buffer_len..self.buffer_len + take].copy_from_slice(&data[..take]);
+            self.buffer_len += take;
+            data = &data[take..];
+
+            if self.buffer_len == 64 {
+                let block: [u8; 64] = self.buffer;
+                self.compress(&block);
+                self.buffer_len = 0;
+            }
+        }
+    }
+
+    /// Pads the message and returns the digest as lowercase hex
+    pub fn finish(mut self) -> String {
+        let bit_len: u64 = self.total_len.wrapping_mul(8);
+        let length_bytes: [u8; 8] = match self.state {
+            State::Md5(_) => bit_len.to_le_bytes(),
+            _ => bit_len.to_be_bytes(),
+        };
+
+        self.update(&[0x80]);
+        while self.buffer_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&length_bytes);
+
+        let bytes: Vec<u8> = match &self.state {
+            State::Md5(words) => words.iter().flat_map(|w| w.to_le_bytes()).collect(),
+            State::Sha1(words) => words.iter().flat_map(|w| w.to_be_bytes()).collect(),
+            State::Sha256(words) => words.iter().flat_map(|w| w.to_be_bytes()).collect(),
+        };
+
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        match &mut self.state {
+            State::Md5(state) => md5_compress(state, block),
+            State::Sha1(state) => sha1_compress(state, block),
+            State::Sha256(state) => sha256_compress(state, block),
+        }
+    }
+}
+
+/// Hashes the contents of a file, reading it in fixed-size chunks
+pub fn file_digest(path: &Path, algorithm: ChecksumAlgorithm) -> io::Result<String> {
+    let mut file: fs::File = fs::File::open(path)?;
+    let mut hasher: Hasher = Hasher::new(algorithm);
+    let mut chunk: Vec<u8> = vec![0; 64 * 1024];
+
+    loop {
+        let read: usize = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&chunk[..read]);
+    }
+
+    Ok(hasher.finish())
+}
+
+fn md5_compress(state: &mut [u32; 4], block: &[u8; 64]) {
+    let m: Vec<u32> = block
+        .chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect();
+    let [mut a, mut b, mut c, mut d] = *state;
+
+    for i in 0..64 {
+        let (f, g) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+        let shift: u32 = MD5_SHIFTS[(i / 16) * 4 + i % 4];
+        let f: u32 = f.wrapping_add(a).wrapping_add(MD5_K[i]).wrapping_add(m[g]);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(f.rotate_left(shift));
+    }
+
+    state[0] = state[0].wrapping_add(a);
+    state[1] = state[1].wrapping_add(b);
+    state[2] = state[2].wrapping_add(c);
+    state[3] = state[3].wrapping_add(d);
+}
+
+fn sha1_compress(state: &mut [u32; 5], block: &[u8; 64]) {
+    let mut w: [u32; 80] = [0; 80];
+    for (i, c) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([c[0], c[1], c[2], c[3]]);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+
+    for (i, word) in w.iter().enumerate() {
+        let (f, k) = match i / 20 {
+            0 => ((b & c) | (!b & d), 0x5a827999),
+            1 => (b ^ c ^ d, 0x6ed9eba1),
+            2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+            _ => (b ^ c ^ d, 0xca62c1d6),
+        };
+        let temp: u32 = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    for (slot, value) in state.iter_mut().zip([a, b, c, d, e]) {
+        *slot = slot.wrapping_add(value);
+    }
+}
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8; 64]) {
+    let mut w: [u32; 64] = [0; 64];
+    for (i, c) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([c[0], c[1], c[2], c[3]]);
+    }
+    for i in 16..64 {
+        let s0: u32 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1: u32 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+
+    for i in 0..64 {
+        let s1: u32 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch: u32 = (e & f) ^ (!e & g);
+        let temp1: u32 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(SHA256_K[i])
+            .wrapping_add(w[i]);
+        let s0: u32 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj: u32 = (a & b) ^ (a & c) ^ (b & c);
+        let temp2: u32 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+
+    for (slot, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *slot = slot.wrapping_add(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [ChecksumAlgorithm; 3] = [ChecksumAlgorithm::Md5, ChecksumAlgorithm::Sha1, ChecksumAlgorithm::Sha256];
+
+    /// The FIPS 180 two-block message, 56 bytes long
+    const MESSAGE_56: &str = "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+
+    /// The FIPS 180 long message, 112 bytes long
+    const MESSAGE_112: &str = "abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+
+    fn digest(algorithm: ChecksumAlgorithm, chunks: &[&[u8]]) -> String {
+        let mut hasher: Hasher = Hasher::new(algorithm);
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finish()
+    }
+
+    /// Each message with its MD5, SHA-1 and SHA-256 digests
+    fn known_answers() -> Vec<(Vec<u8>, [&'static str; 3])> {
+        vec![
+            (b"".to_vec(), [
+                "d41d8cd98f00b204e9800998ecf8427e",
+                "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ]),
+            (b"abc".to_vec(), [
+                "900150983cd24fb0d6963f7d28e17f72",
+                "a9993e364706816aba3e25717850c26c9cd0d89d",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ]),
+            // The longest message whose padding fits in one block
+            (vec![b'a'; 55], [
+                "ef1772b6dff9a122358552954ad0df65",
+                "c1c8bbdc22796e28c0e15163d20899b65621d65a",
+                "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318",
+            ]),
+            // The length no longer fits, so padding takes a second block
+            (MESSAGE_56.as_bytes().to_vec(), [
+                "8215ef0796a20bcaaae116d3876c664a",
+                "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ]),
+            (vec![b'a'; 64], [
+                "014842d480b571495a4a0363793f7367",
+                "0098ba824b5c16427bd7a1122a5a442a25ec644d",
+                "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb",
+            ]),
+            (MESSAGE_112.as_bytes().to_vec(), [
+                "03dd8807a93175fb062dfb55dc7d359c",
+                "a49b2446a02c645bf419f995b67091253a04a259",
+                "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1",
+            ]),
+        ]
+    }
+
+    #[test]
+    fn digests_match_known_answers() {
+        for (message, digests) in known_answers() {
+            for (algorithm, expected) in ALGORITHMS.into_iter().zip(digests) {
+                assert_eq!(digest(algorithm, &[&message]), expected, "{:?} of {} bytes", algorithm, message.len());
+            }
+        }
+    }
+
+    #[test]
+    fn digests_do_not_depend_on_how_input_is_split() {
+        let message: &[u8] = MESSAGE_112.as_bytes();
+        for (algorithm, expected) in ALGORITHMS.into_iter().zip(known_answers()[5].1) {
+            // Splits inside, at and across the 64-byte block boundary
+            let splits: [&[&[u8]]; 3] = [
+                &[&message[..1], &message[1..63], &message[63..]],
+                &[&message[..64], &[], &message[64..]],
+                &[&message[..30], &message[30..100], &message[100..]],
+            ];
+            for chunks in splits {
+                assert_eq!(digest(algorithm, chunks), expected, "{:?}", algorithm);
+            }
+        }
+    }
+
+    /// A million `a`s, fed a thousand at a time
+    #[test]
+    fn digests_the_million_byte_message() {
+        let chunk: Vec<u8> = vec![b'a'; 1000];
+        let chunks: Vec<&[u8]> = vec![&chunk; 1000];
+        let expected: [&str; 3] = [
+            "7707d6ae4e027c70eea2a935c2296f21",
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f",
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+        ];
+        for (algorithm, expected) in ALGORITHMS.into_iter().zip(expected) {
+            assert_eq!(digest(algorithm, &chunks), expected, "{:?}", algorithm);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+/// Builds a sort key that orders names the way people read them: case is
+/// ignored and accented Latin letters sort with their base letter, so `é`
+/// lands next to `e`. The original name breaks ties, keeping the order
+/// deterministic for names that fold to the same key.
+pub fn sort_key(name: &str) -> (String, String) {
+    (fold(name), name.to_string())
+}
+
+/// Lowercases `name` and strips the diacritics of Latin letters
+pub fn fold(name: &str) -> String {
+    let mut folded: String = String::with_capacity(name.len());
+    for c in name.chars().flat_map(char::to_lowercase) {
+        match fold_char(c) {
+            Some(base) => folded.push_str(base),
+            None => folded.push(c),
+        }
+    }
+    folded
+}
+
+fn fold_char(c: char) -> Option<&'static str> {
+    let base: &str = match c {
+        'à'..='å' | 'ā' | 'ă' | 'ą' | 'ǎ' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' | 'ǐ' => "i",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' | 'ơ' | 'ǒ' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' => "s",
+        'ß' => "ss",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' | 'ư' | 'ǔ' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        // Latin Extended Additional, e.g. Vietnamese letters
+        '\u{1ea1}'..='\u{1eb7}' => "a",
+        '\u{1eb9}'..='\u{1ec7}' => "e",
+        '\u{1ec9}'..='\u{1ecb}' => "i",
+        '\u{1ecd}'..='\u{1ee3}' => "o",
+        '\u{1ee5}'..='\u{1ef1}' => "u",
+        '\u{1ef3}'..='\u{1ef9}' => "y",
+        _ => return None,
+    };
+    Some(base)
+}
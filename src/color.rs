@@ -0,0 +1,34 @@
+/// ANSI foreground colors cycled through by `--depth-colors`
+pub const DEPTH_PALETTE: [&str; 6] = ["31", "32", "33", "34", "35", "36"];
+
+/// Wraps text in an ANSI SGR escape sequence
+pub fn paint(text: &str, code: &str) -> String {
+    if text.is_empty() {
+        return String::new();
+    }
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+pub fn depth_color(level: usize) -> &'static str {
+    DEPTH_PALETTE[level % DEPTH_PALETTE.len()]
+}
+
+/// Counts the characters of `text` that take up space on screen, skipping
+/// ANSI SGR escape sequences
+pub fn visible_width(text: &str) -> usize {
+    let mut width: usize = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip up to and including the final `m`
+            for c in chars.by_ref() {
+                if c == 'm' {
+                    break;
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
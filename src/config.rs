@@ -1,54 +1,973 @@
-use clap::Parser;
-use std::path::PathBuf;
+use crate::filter::Filter;
+use crate::pattern::Patterns;
+use crate::size;
+use crate::time::{self, DateBound};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(name = "tree")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(about = "List contents of directories in a tree-like format")]
 #[command(long_about = None)]
+#[command(group(ArgGroup::new("gitignore_mode").args(["gitignore", "show_ignored_only"])))]
 pub struct Config {
-    /// All files are listed
+    /// All files are listed, including dotfiles (`.` and `..` are never shown)
     #[arg(short, long)]
     pub all: bool,
 
+    /// List dotfiles but skip hidden directories such as `.git` and `.cache`;
+    /// -a overrides this and lists everything
+    #[arg(long)]
+    pub ignore_hidden_dirs: bool,
+
+    /// Never list `.git` entries at any depth, even with -a
+    #[arg(long)]
+    pub hide_git: bool,
+
     /// List directories only
     #[arg(short, long)]
     pub dirs_only: bool,
 
+    /// List directories before files
+    #[arg(long)]
+    pub dirsfirst: bool,
+
+    /// Randomize the order of entries with a seeded generator; the same
+    /// SEED gives the same order, and without one a seed is picked and
+    /// printed to stderr
+    #[arg(long, value_name = "SEED", num_args = 0..=1, conflicts_with = "sort")]
+    pub shuffle: Option<Option<u64>>,
+
+    /// Sort by comma-separated keys (name, time, size, extension), ties broken
+    /// by the next key; a `-` suffix reverses a key, as in `size-,name`
+    #[arg(long, value_name = "KEYS", value_delimiter = ',', value_parser = SortKey::parse)]
+    pub sort: Vec<SortKey>,
+
+    /// Sort names ignoring case and accents (`é` next to `e`) instead of by bytes
+    #[arg(long)]
+    pub locale_sort: bool,
+
+    /// Insert a blank line between directories and files (requires --dirsfirst)
+    #[arg(long, requires = "dirsfirst")]
+    pub group_separator: bool,
+
+    /// Lay out sibling files in columns fitting the terminal width (`COLUMNS`
+    /// overrides it)
+    #[arg(long)]
+    pub columns: bool,
+
+    /// List sibling files as one comma-separated entry wrapped to the
+    /// terminal width (`COLUMNS` overrides it)
+    #[arg(long, conflicts_with = "columns")]
+    pub compact_files: bool,
+
+    /// Characters used to draw the indentation lines
+    #[arg(long, value_enum, value_name = "CHARSET", default_value_t = Charset::Utf8)]
+    pub charset: Charset,
+
+    /// Draw ASCII lines when the output is not a terminal; an explicit
+    /// --charset wins
+    #[arg(long)]
+    pub ascii_fallback_on_redirect: bool,
+
+    /// Print every name base64-encoded behind a `b64:` marker
+    #[arg(long)]
+    pub base64_names: bool,
+
+    /// Don't append `/` to directory names
+    #[arg(long)]
+    pub no_trailing_slash: bool,
+
+    /// Doc-friendly output: ASCII lines, no colors, no trailing slashes and
+    /// no report. An explicit --charset, --color or --report-format wins.
+    #[arg(long)]
+    pub plain: bool,
+
+    /// Byte-identical output across machines and runs: name order, no
+    /// dates, sizes or link counts, no absolute root paths and no colors
+    /// unless --color is given
+    #[arg(long)]
+    pub deterministic: bool,
+
     /// Don't print indentation lines
     #[arg(short = 'i', long)]
     pub no_indent: bool,
 
+    /// Indent each level with one tab instead of drawing lines
+    #[arg(long, conflicts_with = "no_indent")]
+    pub use_tabs: bool,
+
     /// Display full file paths
     #[arg(short, long)]
     pub full_path: bool,
 
+    /// Write CHAR between path components in -f and --paths-only output
+    /// instead of the platform's separator (e.g. `\` or `/`)
+    #[arg(long, value_name = "CHAR")]
+    pub path_separator: Option<char>,
+
+    /// List the subdirectory DIR of each path instead of the path itself
+    #[arg(long, value_name = "DIR")]
+    pub root_at: Option<PathBuf>,
+
+    /// Resolve each path argument to its canonical absolute form
+    #[arg(long)]
+    pub canonicalize_roots: bool,
+
+    /// Print TEXT as the root line instead of the path; `{}` in TEXT is
+    /// replaced by the usual `path/`
+    #[arg(long, value_name = "TEXT")]
+    pub tree_root_symbol: Option<String>,
+
+    /// Abbreviate the home directory as `~` in displayed paths
+    #[arg(long)]
+    pub replace_home: bool,
+
+    /// List directories matching PATTERN with a `[...]` marker but do not
+    /// descend into them (repeatable, `|` separates alternatives)
+    #[arg(long, value_name = "PATTERN")]
+    pub no_recurse_into: Vec<String>,
+
+    /// List directories whose contents exceed SIZE (e.g. 500M) with a
+    /// `[too large, N]` marker instead of descending into them
+    #[arg(long, value_name = "SIZE", value_parser = size::parse_size, conflicts_with = "no_metadata")]
+    pub skip_dirs_over: Option<u64>,
+
     /// Ignore files specified in .gitignore
     #[arg(short, long)]
     pub gitignore: bool,
 
-    /// Max display depth of the directory tree
-    #[arg(short = 'L', long, value_name = "LEVEL")]
-    pub max_depth: Option<usize>,
+    /// List only what .gitignore ignores, the inverse of -g (which it
+    /// replaces); other directories are walked to find ignored entries and
+    /// dropped if they hold none
+    #[arg(long)]
+    pub show_ignored_only: bool,
+
+    /// Anchor .gitignore matching at DIR instead of the nearest directory
+    /// containing `.git` (requires -g or --show-ignored-only)
+    #[arg(long, value_name = "DIR", requires = "gitignore_mode")]
+    pub gitignore_root: Option<PathBuf>,
+
+    /// Match .gitignore the way git does: nested files, `.git/info/exclude`,
+    /// `**` and classes, and no re-including below ignored directories
+    /// (requires -g or --show-ignored-only)
+    #[arg(long, requires = "gitignore_mode")]
+    pub strict_gitignore: bool,
+
+    /// Print which .gitignore pattern hid each entry to stderr (requires -g)
+    #[arg(long, requires = "gitignore")]
+    pub explain_ignore: bool,
+
+    /// Which ignore source wins when -g and -I/--exclude-from disagree
+    #[arg(long, value_enum, value_name = "SOURCE", default_value_t = IgnorePriority::Excludes)]
+    pub ignore_priority: IgnorePriority,
+
+    /// Leave dotfiles out of the report counts, even when shown with -a
+    #[arg(long)]
+    pub report_visible_only: bool,
+
+    /// Omit the file and directory report at the end
+    #[arg(long)]
+    pub noreport: bool,
+
+    /// Format of the report printed after each tree
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = ReportFormat::Text,
+        conflicts_with = "noreport"
+    )]
+    pub report_format: ReportFormat,
+
+    /// Count symlinks separately as links in the report instead of as
+    /// their targets
+    #[arg(long)]
+    pub no_dereference_report: bool,
+
+    /// Take the size and type of symlinked files from the links themselves
+    /// instead of their targets
+    #[arg(long)]
+    pub no_dereference_files: bool,
+
+    /// Report how many hidden entries were skipped
+    #[arg(long)]
+    pub show_hidden_summary: bool,
+
+    /// Follow the report with the total size of the files of each
+    /// extension, largest first
+    #[arg(long, conflicts_with = "no_metadata")]
+    pub size_by_ext: bool,
+
+    /// Follow the report with the directories and files found at each depth
+    #[arg(long)]
+    pub per_level_stats: bool,
+
+    /// Report how many entries .gitignore patterns skipped (requires -g)
+    #[arg(long, requires = "gitignore")]
+    pub show_ignored_count: bool,
+
+    /// Print a report per path; `false` merges all paths into one tree
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = ArgAction::Set)]
+    pub separate_reports: bool,
+
+    /// Leave a blank line between the trees of several paths
+    #[arg(long)]
+    pub blank_between_roots: bool,
+
+    /// Print RULE, framed by blank lines, between the trees of several paths
+    #[arg(long, value_name = "RULE", allow_hyphen_values = true)]
+    pub root_separator: Option<String>,
+
+    /// When to use colors
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorMode::Auto)]
+    pub color: ColorMode,
+
+    /// Color the indentation lines by nesting level
+    #[arg(long)]
+    pub depth_colors: bool,
+
+    /// Print a key explaining the active colors after the tree; nothing is
+    /// printed when colors are off
+    #[arg(long)]
+    pub legend: bool,
+
+    /// List only files matching the pattern (`|` separates alternatives)
+    #[arg(short = 'P', long = "pattern", value_name = "PATTERN")]
+    pub match_patterns: Vec<String>,
+
+    /// With -P, show only directories leading to matching files. Directory
+    /// names themselves are never matched, only kept as ancestors.
+    #[arg(long, requires = "match_patterns")]
+    pub only_matching_ancestors: bool,
+
+    /// List only FIFOs (combines with --sockets and --devices; Unix only)
+    #[arg(long, conflicts_with = "exclude_fifos")]
+    pub fifos: bool,
+
+    /// List only sockets (combines with --fifos and --devices; Unix only)
+    #[arg(long, conflicts_with = "exclude_sockets")]
+    pub sockets: bool,
+
+    /// List only block and character devices (combines with --fifos and --sockets; Unix only)
+    #[arg(long, conflicts_with = "exclude_devices")]
+    pub devices: bool,
+
+    /// Leave out FIFOs (Unix only)
+    #[arg(long)]
+    pub exclude_fifos: bool,
+
+    /// Leave out sockets (Unix only)
+    #[arg(long)]
+    pub exclude_sockets: bool,
+
+    /// Leave out block and character devices (Unix only)
+    #[arg(long)]
+    pub exclude_devices: bool,
+
+    /// What to do with a directory symlink leading back into its own path
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = SymlinkLoopPolicy::Mark)]
+    pub symlink_loop_policy: SymlinkLoopPolicy,
+
+    /// Stop following directory symlinks after N of them along one path,
+    /// listing the next with a `[symlink depth]` marker (0 follows none)
+    #[arg(long, value_name = "N", default_value_t = 40)]
+    pub max_symlink_depth: usize,
+
+    /// Mark directories that are mount points with `[mount]` while still
+    /// descending into them (Unix only)
+    #[arg(long)]
+    pub mark_mounts: bool,
+
+    /// Leave out symlinks to files and directories alike
+    #[arg(long, conflicts_with = "only_symlinks")]
+    pub exclude_symlinks: bool,
+
+    /// List only symlinks; real directories are still traversed
+    #[arg(long)]
+    pub only_symlinks: bool,
+
+    /// List only files modified on or after DATE (YYYY-MM-DD[THH:MM[:SS]], UTC)
+    #[arg(long, value_name = "DATE", value_parser = DateBound::parse)]
+    pub since: Option<DateBound>,
+
+    /// List only files modified on or before DATE (YYYY-MM-DD[THH:MM[:SS]], UTC)
+    #[arg(long, value_name = "DATE", value_parser = DateBound::parse)]
+    pub until: Option<DateBound>,
+
+    /// List only entries matching EXPR, e.g. `size>1M && ext==rs && !hidden`.
+    /// Applies to directories too; write `is_dir || ...` to keep traversing.
+    #[arg(long, value_name = "EXPR", value_parser = Filter::parse)]
+    pub filter: Option<Filter>,
+
+    /// Mark directories with nothing to list after filtering with `(empty)`
+    #[arg(long, conflicts_with = "prune")]
+    pub mark_empty_dirs: bool,
+
+    /// Prune empty directories from the output. With -L, a directory at the
+    /// depth limit is kept only if a visible file lies somewhere below it.
+    #[arg(long)]
+    pub prune: bool,
+
+    /// List only files with one of these extensions (comma-separated)
+    #[arg(long, value_name = "EXTS", value_delimiter = ',', conflicts_with = "exclude_ext")]
+    pub include_ext: Vec<String>,
+
+    /// Do not list files with any of these extensions (comma-separated)
+    #[arg(long, value_name = "EXTS", value_delimiter = ',')]
+    pub exclude_ext: Vec<String>,
+
+    /// How -P, -I, --exclude-from and --no-recurse-into patterns are read.
+    /// Regexes match anywhere in the name and support literals, `.`, classes
+    /// like `[a-z]` and `[^.]`, `\d \w \s` and their capitals, `^ $`, groups
+    /// (`(...)`, `(?:...)`), `|` and `* + ? {m,n}`. Lookaround, named groups,
+    /// inline flags, backreferences, `\b`, `\p{..}` and `[:alpha:]` are
+    /// rejected
+    #[arg(long, value_enum, value_name = "SYNTAX", default_value_t = PatternSyntax::Glob)]
+    pub pattern_syntax: PatternSyntax,
+
+    /// Whether -P and -I match names case-sensitively; `auto` follows the
+    /// filesystem of each root, like a case-insensitive APFS volume
+    #[arg(long, value_enum, value_name = "CASE", default_value_t = FsCase::Auto)]
+    pub fs_case: FsCase,
+
+    /// Do not list entries matching the pattern (`|` separates alternatives)
+    #[arg(short = 'I', long = "ignore", value_name = "PATTERN")]
+    pub ignore_patterns: Vec<String>,
+
+    /// Read exclude patterns from a file, one glob per line
+    #[arg(long, value_name = "FILE")]
+    pub exclude_from: Option<PathBuf>,
+
+    /// Omit the root header lines; with `--separate-reports false` the
+    /// entries of all roots form one continuous listing
+    #[arg(long)]
+    pub entries_only: bool,
+
+    /// Color file names by git status: untracked red, modified yellow, staged green
+    #[arg(long)]
+    pub color_by_git_status: bool,
+
+    /// Tag files modified since the system booted with `[recent]`
+    #[arg(long)]
+    pub recent: bool,
+
+    /// With --recent, tag files modified within DURATION instead of since boot
+    #[arg(long, value_name = "DURATION", value_parser = time::parse_duration, requires = "recent")]
+    pub recent_window: Option<Duration>,
+
+    /// Color files not modified within DURATION (e.g. 90d, 12h, 2w) in dim red
+    #[arg(long, value_name = "DURATION", value_parser = time::parse_duration)]
+    pub stale_after: Option<Duration>,
+
+    /// Max display depth of the directory tree; a comma-separated list
+    /// gives each path its own depth, in order
+    #[arg(short = 'L', long, value_name = "LEVEL", value_delimiter = ',')]
+    pub max_depth: Vec<usize>,
+
+    /// Print the size of each entry in bytes
+    #[arg(short = 's', long)]
+    pub size: bool,
+
+    /// Show directory sizes as the total of their listed contents, counted
+    /// in disk blocks like `du` (implies -s)
+    #[arg(long)]
+    pub du: bool,
+
+    /// What directory sizes show: the directory entry itself, or the total
+    /// of its listed contents (implies -s; always `recursive` with --du)
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = DirSize::Entry)]
+    pub dir_size: DirSize,
+
+    /// With --du, count file lengths instead of the disk blocks they use
+    #[arg(long, requires = "du")]
+    pub apparent_size: bool,
+
+    /// Print the --du total in human-readable units such as `4.2 MB`
+    #[arg(long, requires = "du")]
+    pub report_human: bool,
+
+    /// Show sizes in powers of 1000 (kB, MB, GB) instead of bytes, including
+    /// the --du total (implies -s)
+    #[arg(long)]
+    pub si: bool,
+
+    /// Walk names only: never read sizes or dates, showing `-` in their columns
+    #[arg(long, conflicts_with_all = ["since", "until"])]
+    pub no_metadata: bool,
+
+    /// Show the number of lines of each regular file
+    #[arg(long)]
+    pub lines: bool,
+
+    /// Show the number of hard links of each entry (Unix only)
+    #[arg(long, conflicts_with = "no_metadata")]
+    pub link_count: bool,
+
+    /// Pad the metadata columns of -s, -D and the like to a common width
+    /// within each directory, so that sibling names start in one column
+    #[arg(long, conflicts_with_all = ["columns", "compact_files"])]
+    pub align_names: bool,
+
+    /// Stop counting --lines at N and show `N+ lines`
+    #[arg(long, value_name = "N", requires = "lines")]
+    pub max_line_count: Option<usize>,
+
+    /// Show a checksum of each regular file's contents
+    #[arg(long, value_enum, value_name = "ALGO")]
+    pub checksum: Option<ChecksumAlgorithm>,
+
+    /// Flag text files that are not valid UTF-8 with `[non-utf8]`
+    #[arg(long)]
+    pub encoding_detect: bool,
+
+    /// Tag files whose content looks binary with `[bin]`
+    #[arg(long)]
+    pub mark_binary: bool,
+
+    /// Mark files whose name also appears elsewhere in the tree with `[dup N]`
+    #[arg(long)]
+    pub duplicate_names: bool,
+
+    /// List files grouped under one heading per extension instead of the tree
+    #[arg(long, conflicts_with_all = ["json", "manifest"])]
+    pub group_by_ext: bool,
+
+    /// Order of the --group-by-ext headings
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = ExtGroupOrder::Name, requires = "group_by_ext")]
+    pub ext_group_order: ExtGroupOrder,
+
+    /// Print the path of every listed entry, one per line, instead of the tree
+    #[arg(long, conflicts_with_all = ["json", "manifest", "group_by_ext"])]
+    pub paths_only: bool,
+
+    /// With --paths-only, strip the directory prefix shared by all paths and
+    /// print it once on a leading `base:` line
+    #[arg(long, requires = "paths_only")]
+    pub trim_common_prefix: bool,
+
+    /// Print a single digest of the listed structure instead of the tree;
+    /// with --checksum, file contents are included
+    #[arg(long, conflicts_with = "json")]
+    pub manifest: bool,
+
+    /// Show comments from a `.tree.info` file in each root beneath matching entries
+    #[arg(long)]
+    pub info: bool,
+
+    /// Run CMD through the shell for every listed regular file, with `{}`
+    /// replaced by the quoted path; failures are marked with their exit code
+    #[arg(long, value_name = "CMD", conflicts_with = "watch")]
+    pub exec: Option<String>,
+
+    /// Show the output of --exec beneath each file
+    #[arg(long, requires = "exec")]
+    pub exec_output: bool,
+
+    /// Run at most N --exec commands at once (default: one per CPU)
+    #[arg(long, value_name = "N", requires = "exec")]
+    pub exec_jobs: Option<usize>,
+
+    /// Print the tree as JSON
+    #[arg(short = 'J', long)]
+    pub json: bool,
+
+    /// Print JSON on a single line without indentation
+    #[arg(long, requires = "json")]
+    pub json_compact: bool,
+
+    /// Print the date of last modification
+    #[arg(short = 'D', long = "date")]
+    pub show_date: bool,
 
-    /// Output tree to a file
+    /// Format dates with strftime-style specifiers (implies -D, overrides --time-style)
+    #[arg(long, value_name = "FMT")]
+    pub timefmt: Option<String>,
+
+    /// Format dates with a preset style (implies -D)
+    #[arg(long, value_enum, value_name = "STYLE")]
+    pub time_style: Option<TimeStyle>,
+
+    /// Show at most N files per directory, followed by `… (X more files)`;
+    /// directories are never capped
+    #[arg(long, value_name = "N")]
+    pub max_files_per_dir: Option<usize>,
+
+    /// Show only the first N entries in breadth-first order, marking each
+    /// directory that lost entries with `… and X more`
+    #[arg(long, value_name = "N")]
+    pub max_entries_total: Option<usize>,
+
+    /// Join single-child directory chains shallower than DEPTH into one line
+    #[arg(long, value_name = "DEPTH")]
+    pub flatten: Option<usize>,
+
+    /// Join every chain of directories holding only a single subdirectory
+    #[arg(long, conflicts_with = "flatten")]
+    pub collapse: bool,
+
+    /// Compare against another directory, marking entries only in PATH with
+    /// `[+]` and only in DIR with `[-]`
+    #[arg(long, value_name = "DIR")]
+    pub diff: Option<PathBuf>,
+
+    /// With --diff, also mark files whose size or mtime differ with `[M]`
+    #[arg(long, requires = "diff")]
+    pub diff_content: bool,
+
+    /// Browse the tree in the terminal, expanding and collapsing directories
+    /// with the arrow keys; `q` quits without printing anything (Unix only)
+    #[arg(long, conflicts_with_all = ["watch", "output", "output_dir", "json", "manifest", "group_by_ext", "paths_only"])]
+    pub interactive: bool,
+
+    /// Keep running and redraw the tree whenever it changes (Ctrl-C to exit).
+    /// Every 500ms the listed entries are checked for a new modification time
+    /// or size, and the tree is listed again only when one changed. Changes
+    /// that touch no listed entry, such as below the -L limit, are missed
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Keep going past unreadable directories and entries, mark them inline
+    /// and print the number of errors at the end
+    #[arg(long)]
+    pub ignore_errors: bool,
+
+    /// With --ignore-errors, also list every error
+    #[arg(long, requires = "ignore_errors")]
+    pub verbose_errors: bool,
+
+    /// Log each directory entered, each filter decision and per-directory
+    /// timings to stderr
+    #[arg(long)]
+    pub debug: bool,
+
+    /// Write the errors skipped by --ignore-errors as a JSON array of
+    /// `path`/`message` objects to FILE (`-` for stderr)
+    #[arg(long, value_name = "FILE", requires = "ignore_errors")]
+    pub report_errors_json: Option<PathBuf>,
+
+    /// Also write the totals, the size per extension and any errors as a
+    /// JSON object to FILE, creating its parent directories
+    #[arg(long, value_name = "FILE", conflicts_with = "no_metadata")]
+    pub stats_file: Option<PathBuf>,
+
+    /// Abort if the rendered output grows beyond MB megabytes
+    #[arg(long, value_name = "MB")]
+    pub max_memory: Option<u64>,
+
+    /// Line ending of the written output
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = LineEnding::Lf)]
+    pub line_ending: LineEnding,
+
+    /// Cut the output after the last complete line that fits in N bytes,
+    /// truncation notice included
+    #[arg(long, value_name = "N")]
+    pub limit_output_bytes: Option<usize>,
+
+    /// Output tree to a file (`-` writes to stdout)
     #[arg(short, long, value_name = "FILE")]
     pub output: Option<PathBuf>,
 
+    /// Wrap the whole output in a Markdown code block or an HTML `<pre>` block
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = Wrap::None)]
+    pub wrap: Wrap,
+
+    /// Leave the -o file untouched when its content would not change
+    #[arg(long, requires = "output")]
+    pub output_if_changed: bool,
+
+    /// Split the -o file into numbered parts of at most N bytes each
+    /// (`tree.001.txt`, `tree.002.txt`, ...), cut at line boundaries
+    #[arg(long, value_name = "N", requires = "output", conflicts_with = "output_if_changed")]
+    pub output_split_size: Option<usize>,
+
+    /// Write each path's tree to `DIR/<name>.txt`, creating DIR if needed
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "watch"])]
+    pub output_dir: Option<PathBuf>,
+
     /// Paths to list (default: current directory)
     #[arg(value_name = "PATH")]
     pub paths: Vec<PathBuf>,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    /// Box-drawing characters: `├── `, `└── `, `│`
+    Utf8,
+    /// Plain ASCII: `|-- `, `` `-- ``, `|`
+    Ascii,
+    /// UTF-8 on a terminal, ASCII when the output is redirected
+    Auto,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternSyntax {
+    /// `*` and `?` wildcards, `|` separating alternatives
+    Glob,
+    /// Regular expressions matched anywhere in the name
+    Regex,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsCase {
+    /// Probe whether the root's filesystem tells names apart by case
+    Auto,
+    /// Names differing in case are different
+    Sensitive,
+    /// Names differing only in case are the same
+    Insensitive,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirSize {
+    /// Size of the directory entry, as GNU tree shows it
+    Entry,
+    /// Total of everything listed beneath the directory
+    Recursive,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtGroupOrder {
+    /// Alphabetically by extension
+    Name,
+    /// Largest group first, ties alphabetically
+    Count,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IgnorePriority {
+    /// -I and --exclude-from apply after .gitignore, so they always exclude
+    Excludes,
+    /// .gitignore applies last, so its negations re-include excluded entries
+    Gitignore,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortField {
+    /// Entry name, folded with --locale-sort
+    Name,
+    /// Modification time, oldest first
+    Time,
+    /// Size in bytes, smallest first
+    Size,
+    /// Extension, case-insensitively; names without one come first
+    Extension,
+}
+
+/// One key of --sort
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl SortKey {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (name, descending): (&str, bool) = match text.strip_suffix('-') {
+            Some(name) => (name, true),
+            None => (text, false),
+        };
+        let field: SortField = SortField::from_str(name.trim(), true)
+            .map_err(|_| format!("invalid sort key `{}`, expected name, time, size or extension", text))?;
+        Ok(Self { field, descending })
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymlinkLoopPolicy {
+    /// Abort with an error
+    Error,
+    /// Leave the link out
+    Skip,
+    /// List the link with a `[recursive]` marker, without following it
+    Mark,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    /// End lines with `\n`
+    Lf,
+    /// End lines with `\r\n`, for Windows tools
+    Crlf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wrap {
+    /// Print the output as is
+    None,
+    /// Fence the output with triple backticks
+    Markdown,
+    /// Put the output, HTML-escaped, in a `<pre>` block
+    Html,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// 2024-01-15
+    Iso,
+    /// 2024-01-15 14:32
+    LongIso,
+    /// 2024-01-15 14:32:07 +0000
+    FullIso,
+    /// 3 days ago
+    Relative,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
 impl Config {
     pub fn parse_args() -> Self {
-        let mut config: Config = Self::parse();
+        Self::parse_args_from(std::env::args_os())
+    }
+
+    /// Parses `args`, the program name first, and applies the presets and
+    /// defaults that depend on what was given explicitly
+    pub fn parse_args_from<I, T>(args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches: ArgMatches = Self::command().get_matches_from(args);
+        let mut config: Config = Self::from_arg_matches(&matches).unwrap_or_else(|err: clap::Error| err.exit());
+
+        let is_explicit = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        if config.ascii_fallback_on_redirect && !is_explicit("charset") {
+            config.charset = Charset::Auto;
+        }
+
+        // The --plain preset only fills in what was not given explicitly
+        if config.plain {
+            if !is_explicit("charset") {
+                config.charset = Charset::Ascii;
+            }
+            if !is_explicit("color") {
+                config.color = ColorMode::Never;
+            }
+            if !is_explicit("report_format") {
+                config.noreport = true;
+            }
+            config.no_trailing_slash = true;
+        }
+
+        // --deterministic drops everything that depends on when, where or
+        // on which filesystem the tree is listed
+        if config.deterministic {
+            config.sort.clear();
+            config.shuffle = None;
+            config.show_date = false;
+            config.time_style = None;
+            config.stale_after = None;
+            config.recent = false;
+            config.size = false;
+            config.du = false;
+            config.dir_size = DirSize::Entry;
+            config.apparent_size = false;
+            config.report_human = false;
+            config.si = false;
+            config.link_count = false;
+            config.full_path = false;
+            config.canonicalize_roots = false;
+            config.replace_home = false;
+            if !is_explicit("color") {
+                config.color = ColorMode::Never;
+            }
+            if config.charset == Charset::Auto {
+                config.charset = Charset::Utf8;
+            }
+            config.line_ending = LineEnding::Lf;
+        }
+
+        // A shuffle without a seed gets one from the clock, printed so the
+        // run can be repeated
+        if config.shuffle == Some(None) {
+            let seed: u64 = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed: Duration| elapsed.as_nanos() as u64);
+            eprintln!("shuffle seed: {}", seed);
+            config.shuffle = Some(Some(seed));
+        }
 
         // If no paths provided, use current directory
         if config.paths.is_empty() {
             config.paths.push(PathBuf::from("."));
         }
 
+        // One -L value covers every path; otherwise there is one per path
+        if config.max_depth.len() > 1 && config.max_depth.len() != config.paths.len() {
+            Self::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    format!(
+                        "-L has {} levels but {} paths were given; pass one level or one per path",
+                        config.max_depth.len(),
+                        config.paths.len()
+                    ),
+                )
+                .exit();
+        }
+
+        // Expand a leading `~` for paths that did not go through a shell
+        if let Some(home_dir) = std::env::var_os("HOME") {
+            for path in &mut config.paths {
+                *path = expand_tilde(path, Path::new(&home_dir));
+            }
+            if let Some(diff) = &mut config.diff {
+                *diff = expand_tilde(diff, Path::new(&home_dir));
+            }
+        }
+
+        // Re-root every path at the same subdirectory
+        if let Some(root_at) = &config.root_at {
+            if root_at.is_absolute() {
+                Self::command()
+                    .error(ErrorKind::ValueValidation, "--root-at takes a path relative to each root")
+                    .exit();
+            }
+            for path in &mut config.paths {
+                let subdir: PathBuf = path.join(root_at);
+                if !subdir.is_dir() {
+                    Self::command()
+                        .error(
+                            ErrorKind::ValueValidation,
+                            format!("--root-at: {} is not a directory", subdir.display()),
+                        )
+                        .exit();
+                }
+                *path = subdir;
+            }
+        }
+
+        // Resolve symlinks and `..` in the roots before anything is walked
+        if config.canonicalize_roots {
+            for path in &mut config.paths {
+                match path.canonicalize() {
+                    Ok(canonical) => *path = canonical,
+                    Err(err) => Self::command()
+                        .error(
+                            ErrorKind::Io,
+                            format!("cannot canonicalize {}: {}", path.display(), err),
+                        )
+                        .exit(),
+                }
+            }
+        }
+
+        // Extensions compare case-insensitively and may be given as `.rs`
+        for ext in config.include_ext.iter_mut().chain(config.exclude_ext.iter_mut()) {
+            *ext = ext.trim_start_matches('.').to_lowercase();
+        }
+
+        // --show-ignored-only loads the same patterns as -g and inverts
+        // their decision when filtering
+        if config.show_ignored_only {
+            config.gitignore = true;
+        }
+
+        // --du sizes are always rolled up, and rolled-up sizes are shown
+        if config.du {
+            config.dir_size = DirSize::Recursive;
+        }
+        if config.dir_size == DirSize::Recursive || config.si {
+            config.size = true;
+        }
+
+        // Picking a date format implies showing dates
+        if config.timefmt.is_some() || config.time_style.is_some() {
+            config.show_date = true;
+        }
+
+        // Exclude files add to the inline -I patterns
+        if let Some(exclude_from) = &config.exclude_from {
+            match fs::read_to_string(exclude_from) {
+                Ok(contents) => config.ignore_patterns.extend(
+                    contents
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(String::from),
+                ),
+                Err(err) => Self::command()
+                    .error(
+                        ErrorKind::Io,
+                        format!("cannot read {}: {}", exclude_from.display(), err),
+                    )
+                    .exit(),
+            }
+        }
+
+        // --no-metadata leaves nothing for size and mtime conditions to test
+        if config.no_metadata && config.filter.as_ref().is_some_and(Filter::needs_metadata) {
+            Self::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--filter conditions on size or mtime cannot be used with --no-metadata",
+                )
+                .exit();
+        }
+
+        // Reject invalid regexes before walking anything
+        for patterns in [&config.match_patterns, &config.ignore_patterns, &config.no_recurse_into] {
+            if let Err(err) = Patterns::new(patterns, config.pattern_syntax, false) {
+                Self::command().error(ErrorKind::ValueValidation, err).exit();
+            }
+        }
+
         config
     }
 }
+
+fn expand_tilde(path: &Path, home_dir: &Path) -> PathBuf {
+    let mut components = path.components();
+    match components.next() {
+        Some(Component::Normal(first)) if first == "~" => {
+            let rest: &Path = components.as_path();
+            if rest.as_os_str().is_empty() {
+                home_dir.to_path_buf()
+            } else {
+                home_dir.join(rest)
+            }
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_drops_shuffle() {
+        let config: Config = Config::parse_args_from(["tree", "--deterministic", "--shuffle"]);
+        assert_eq!(config.shuffle, None);
+
+        let config: Config = Config::parse_args_from(["tree", "--deterministic", "--shuffle", "7"]);
+        assert_eq!(config.shuffle, None);
+
+        let config: Config = Config::parse_args_from(["tree", "--shuffle", "7"]);
+        assert_eq!(config.shuffle, Some(Some(7)));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::node::TreeNode;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// How an entry of a `--diff` tree relates to the other tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffMark {
+    /// Only present in the primary tree
+    Added,
+    /// Only present in the other tree
+    Removed,
+    /// A file present in both trees whose size or mtime differs
+    Modified,
+}
+
+impl DiffMark {
+    pub fn marker(self) -> &'static str {
+        match self {
+            DiffMark::Added => "[+]",
+            DiffMark::Removed => "[-]",
+            DiffMark::Modified => "[M]",
+        }
+    }
+
+    pub fn color(self) -> &'static str {
+        match self {
+            DiffMark::Added => "32",
+            DiffMark::Removed => "31",
+            DiffMark::Modified => "33",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DiffMark::Added => "added",
+            DiffMark::Removed => "removed",
+            DiffMark::Modified => "modified",
+        }
+    }
+}
+
+/// Merges `other` into `primary` by entry name, marking every entry that
+/// exists on one side only. Entries present in both trees stay unmarked.
+/// When the same name is a file on one side and a directory on the other,
+/// both entries are kept: the primary one as added and the other one as
+/// removed. With `compare_content`, files in both trees whose size or
+/// modification time differ are marked as modified. `sort` puts the merged
+/// children of every directory back in listing order.
+pub fn merge(primary: &mut TreeNode, other: TreeNode, compare_content: bool, sort: &dyn Fn(&mut Vec<TreeNode>)) {
+    // Directories present on both sides wait on a stack instead of recursing
+    let mut pending: Vec<(&mut TreeNode, TreeNode)> = vec![(primary, other)];
+    while let Some((primary, mut other)) = pending.pop() {
+        // Names are unique among the entries of one directory, so each
+        // child finds its counterpart by name and kind; matched ones are
+        // taken out and the rest were removed
+        let by_name: HashMap<(String, bool), usize> = other
+            .children
+            .iter()
+            .enumerate()
+            .map(|(index, node): (usize, &TreeNode)| ((node.name.clone(), node.is_dir), index))
+            .collect();
+        let mut others: Vec<Option<TreeNode>> = std::mem::take(&mut other.children).into_iter().map(Some).collect();
+        let mut counterparts: HashMap<String, TreeNode> = HashMap::new();
+
+        for child in &mut primary.children {
+            let counterpart: Option<TreeNode> = by_name
+                .get(&(child.name.clone(), child.is_dir))
+                .and_then(|&index: &usize| others[index].take());
+
+            match counterpart {
+                Some(counterpart) => {
+                    if child.is_dir {
+                        counterparts.insert(child.name.clone(), counterpart);
+                    } else if compare_content && metadata_differs(&child.path, &counterpart.path) {
+                        child.diff = Some(DiffMark::Modified);
+                    }
+                }
+                None => mark_subtree(child, DiffMark::Added),
+            }
+        }
+
+        for mut removed in others.into_iter().flatten() {
+            mark_subtree(&mut removed, DiffMark::Removed);
+            primary.children.push(removed);
+        }
+
+        sort(&mut primary.children);
+        for child in &mut primary.children {
+            if !child.is_dir || child.diff.is_some() {
+                continue;
+            }
+            if let Some(counterpart) = counterparts.remove(&child.name) {
+                pending.push((child, counterpart));
+            }
+        }
+    }
+}
+
+fn mark_subtree(node: &mut TreeNode, mark: DiffMark) {
+    let mut stack: Vec<&mut TreeNode> = vec![node];
+    while let Some(node) = stack.pop() {
+        node.diff = Some(mark);
+        stack.extend(node.children.iter_mut());
+    }
+}
+
+fn metadata_differs(primary: &Path, other: &Path) -> bool {
+    match (fs::metadata(primary), fs::metadata(other)) {
+        (Ok(primary), Ok(other)) => {
+            primary.len() != other.len() || primary.modified().ok() != other.modified().ok()
+        }
+        // An unreadable side cannot be shown to match
+        _ => true,
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// How much of each file `--encoding-detect` and `--mark-binary` inspect
+const SAMPLE_SIZE: usize = 8 * 1024;
+
+/// Extensions of formats that are binary by design and never checked
+const BINARY_EXTENSIONS: &[&str] = &[
+    "7z", "a", "bin", "bmp", "class", "dll", "dylib", "exe", "gif", "gz", "ico", "jar", "jpeg",
+    "jpg", "mp3", "mp4", "o", "pdf", "png", "rlib", "so", "tar", "wasm", "webp", "xz", "zip",
+];
+
+/// Returns true if the start of a text file is not valid UTF-8. Binary files,
+/// recognized by extension or a NUL byte in the sample, are never flagged.
+pub fn is_non_utf8(path: &Path) -> bool {
+    let is_binary_extension: bool = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext: String| BINARY_EXTENSIONS.contains(&ext.as_str()));
+    if is_binary_extension {
+        return false;
+    }
+
+    let Some(sample) = read_sample(path) else {
+        return false;
+    };
+    if sample.contains(&0) {
+        return false;
+    }
+
+    match std::str::from_utf8(&sample) {
+        Ok(_) => false,
+        // A character cut off by the end of the sample is not an error
+        Err(err) => err.error_len().is_some(),
+    }
+}
+
+/// Returns true if the start of a file looks like binary data: it holds a
+/// NUL byte, or more than a tenth of it are control characters that text
+/// does not use. Unreadable and empty files count as text.
+pub fn is_probably_binary(path: &Path) -> bool {
+    let Some(sample) = read_sample(path) else {
+        return false;
+    };
+    if sample.contains(&0) {
+        return true;
+    }
+
+    let control: usize = sample
+        .iter()
+        .filter(|&&byte: &&u8| (byte < 0x20 && !matches!(byte, b'\t' | b'\n' | 0x0c | b'\r' | 0x1b)) || byte == 0x7f)
+        .count();
+    control * 10 > sample.len()
+}
+
+fn read_sample(path: &Path) -> Option<Vec<u8>> {
+    let mut sample: Vec<u8> = Vec::with_capacity(SAMPLE_SIZE);
+    fs::File::open(path)
+        .and_then(|file: fs::File| file.take(SAMPLE_SIZE as u64).read_to_end(&mut sample))
+        .ok()?;
+    Some(sample)
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as padded standard base64 (RFC 4648)
+pub fn base64(bytes: &[u8]) -> String {
+    let mut output: String = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value: u32 = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |value: u32, (index, &byte)| value | u32::from(byte) << (16 - 8 * index));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(BASE64_ALPHABET[(value >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
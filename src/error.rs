@@ -1,10 +1,17 @@
 use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum TreeError {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
+
+    #[error("output exceeds the --max-memory limit of {0} MB")]
+    MemoryLimit(u64),
+
+    #[error("symlink loop at {}", .0.display())]
+    SymlinkLoop(PathBuf),
 }
 
 pub type TreeResult<T> = Result<T, TreeError>;
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// What running the `--exec` command for one file produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecResult {
+    /// Exit code, `None` if the command could not start or was killed
+    pub code: Option<i32>,
+    /// Standard output followed by standard error
+    pub output: String,
+}
+
+impl ExecResult {
+    pub fn succeeded(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Runs `command` once per path, with `{}` replaced by the quoted path, on at
+/// most `jobs` threads at a time
+pub fn run_all(command: &str, paths: &[PathBuf], jobs: usize) -> HashMap<PathBuf, ExecResult> {
+    let next: AtomicUsize = AtomicUsize::new(0);
+    let results: Mutex<HashMap<PathBuf, ExecResult>> = Mutex::new(HashMap::new());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, paths.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result: ExecResult = run_one(command, path);
+                    results
+                        .lock()
+                        .expect("no worker panics while holding the lock")
+                        .insert(path.clone(), result);
+                }
+            });
+        }
+    });
+
+    results.into_inner().expect("no worker panics while holding the lock")
+}
+
+fn run_one(command: &str, path: &Path) -> ExecResult {
+    let command_line: String = command.replace("{}", &shell_quote(&path.to_string_lossy()));
+
+    #[cfg(windows)]
+    let mut shell: Command = {
+        let mut shell: Command = Command::new("cmd");
+        shell.arg("/C").arg(&command_line);
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell: Command = {
+        let mut shell: Command = Command::new("sh");
+        shell.arg("-c").arg(&command_line);
+        shell
+    };
+
+    match shell.stdin(Stdio::null()).output() {
+        Ok(Output { status, stdout, stderr }) => {
+            let mut output: String = String::from_utf8_lossy(&stdout).to_string();
+            output.push_str(&String::from_utf8_lossy(&stderr));
+            ExecResult {
+                code: status.code(),
+                output,
+            }
+        }
+        Err(err) => ExecResult {
+            code: None,
+            output: err.to_string(),
+        },
+    }
+}
+
+/// Quotes a path so the shell passes it through as a single argument
+#[cfg(not(windows))]
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn shell_quote(text: &str) -> String {
+    format!("\"{}\"", text)
+}
@@ -0,0 +1,40 @@
+use std::fs;
+
+/// Special (non-regular, non-directory) file kinds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialKind {
+    Fifo,
+    Socket,
+    Device,
+}
+
+impl SpecialKind {
+    #[cfg(unix)]
+    pub fn of(file_type: fs::FileType) -> Option<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_fifo() {
+            Some(SpecialKind::Fifo)
+        } else if file_type.is_socket() {
+            Some(SpecialKind::Socket)
+        } else if file_type.is_block_device() || file_type.is_char_device() {
+            Some(SpecialKind::Device)
+        } else {
+            None
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn of(_file_type: fs::FileType) -> Option<Self> {
+        None
+    }
+
+    /// Classify suffix appended to the entry name
+    pub fn marker(self) -> char {
+        match self {
+            SpecialKind::Fifo => '|',
+            SpecialKind::Socket => '=',
+            SpecialKind::Device => '#',
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use crate::pattern::glob_match;
+use crate::size::parse_size;
+use crate::time::DateBound;
+use std::time::SystemTime;
+
+/// A `--filter` predicate such as `size>1M && ext==rs && !hidden`.
+///
+/// Conditions combine with `&&`, `||`, `!` and parentheses, `&&` binding
+/// tighter than `||`. The attributes are:
+///
+/// - `name` and `ext`, compared with `==` or `!=`; `name` takes a glob and
+///   `ext` is compared case-insensitively without the leading dot
+/// - `size`, compared with `== != < <= > >=` against e.g. `512`, `10K`, `1.5M`
+/// - `mtime`, compared against a UTC date (`YYYY-MM-DD[THH:MM[:SS]]`) that
+///   covers its whole day, minute or second
+/// - `is_dir` and `hidden`, used on their own as conditions
+///
+/// Values containing spaces or operator characters go in double quotes.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+/// The attributes of one entry a filter is evaluated against
+#[derive(Debug, Clone, Copy)]
+pub struct EntryAttributes<'a> {
+    pub name: &'a str,
+    /// Lowercase extension without the dot, empty if there is none
+    pub ext: &'a str,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub is_dir: bool,
+    pub hidden: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    IsDir,
+    Hidden,
+    Name { equal: bool, glob: String },
+    Ext { equal: bool, ext: String },
+    Size(Comparison, u64),
+    Mtime(Comparison, DateBound),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Compare(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens: Vec<Token> = tokenize(text)?;
+        let mut parser: Parser = Parser { tokens, pos: 0 };
+        let expr: Expr = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {} in filter", describe(token)));
+        }
+        Ok(Self { expr })
+    }
+
+    /// Whether evaluating the filter needs an entry's size or modification
+    /// time, so callers can skip the metadata lookup otherwise
+    pub fn needs_metadata(&self) -> bool {
+        self.expr.needs_metadata()
+    }
+
+    pub fn matches(&self, entry: &EntryAttributes) -> bool {
+        self.expr.matches(entry)
+    }
+}
+
+impl Expr {
+    fn needs_metadata(&self) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => left.needs_metadata() || right.needs_metadata(),
+            Expr::Not(inner) => inner.needs_metadata(),
+            Expr::Size(..) | Expr::Mtime(..) => true,
+            Expr::IsDir | Expr::Hidden | Expr::Name { .. } | Expr::Ext { .. } => false,
+        }
+    }
+
+    fn matches(&self, entry: &EntryAttributes) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(entry) && right.matches(entry),
+            Expr::Or(left, right) => left.matches(entry) || right.matches(entry),
+            Expr::Not(inner) => !inner.matches(entry),
+            Expr::IsDir => entry.is_dir,
+            Expr::Hidden => entry.hidden,
+            Expr::Name { equal, glob } => glob_match(glob, entry.name) == *equal,
+            Expr::Ext { equal, ext } => (entry.ext == ext) == *equal,
+            Expr::Size(comparison, size) => match comparison {
+                Comparison::Equal => entry.size == *size,
+                Comparison::NotEqual => entry.size != *size,
+                Comparison::Less => entry.size < *size,
+                Comparison::LessOrEqual => entry.size <= *size,
+                Comparison::Greater => entry.size > *size,
+                Comparison::GreaterOrEqual => entry.size >= *size,
+            },
+            Expr::Mtime(comparison, date) => entry.modified.is_some_and(|modified: SystemTime| {
+                // A date names a period, so `<` means before it starts and
+                // `>` means after it ends
+                let after_start: bool = date.is_on_or_after(modified);
+                let before_end: bool = date.is_on_or_before(modified);
+                match comparison {
+                    Comparison::Equal => after_start && before_end,
+                    Comparison::NotEqual => !(after_start && before_end),
+                    Comparison::Less => !after_start,
+                    Comparison::LessOrEqual => before_end,
+                    Comparison::Greater => !before_end,
+                    Comparison::GreaterOrEqual => after_start,
+                }
+            }),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token: Option<Token> = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr: Expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr: Expr = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Open) => {
+                let expr: Expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    Some(token) => Err(format!("expected `)` in filter, found {}", describe(&token))),
+                    None => Err("unclosed `(` in filter".to_string()),
+                }
+            }
+            Some(Token::Word(attribute)) => self.parse_condition(&attribute),
+            Some(token) => Err(format!("expected a condition in filter, found {}", describe(&token))),
+            None => Err("filter ends where a condition was expected".to_string()),
+        }
+    }
+
+    fn parse_condition(&mut self, attribute: &str) -> Result<Expr, String> {
+        match attribute {
+            "is_dir" => return Ok(Expr::IsDir),
+            "hidden" => return Ok(Expr::Hidden),
+            "name" | "ext" | "size" | "mtime" => {}
+            _ => {
+                return Err(format!(
+                    "unknown filter attribute `{}`, expected name, ext, size, mtime, is_dir or hidden",
+                    attribute
+                ));
+            }
+        }
+
+        let Some(Token::Compare(comparison)) = self.next() else {
+            return Err(format!("expected a comparison after `{}` in filter", attribute));
+        };
+        let Some(Token::Word(value)) = self.next() else {
+            return Err(format!("expected a value after `{}` comparison in filter", attribute));
+        };
+
+        let equal: bool = match comparison {
+            Comparison::Equal => true,
+            Comparison::NotEqual => false,
+            _ if attribute == "name" || attribute == "ext" => {
+                return Err(format!("`{}` can only be compared with == or != in filter", attribute));
+            }
+            _ => true,
+        };
+        Ok(match attribute {
+            "name" => Expr::Name { equal, glob: value },
+            "ext" => Expr::Ext {
+                equal,
+                ext: value.trim_start_matches('.').to_lowercase(),
+            },
+            "size" => Expr::Size(comparison, parse_size(&value)?),
+            _ => Expr::Mtime(comparison, DateBound::parse(&value)?),
+        })
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token: Token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' | '|' => {
+                if chars.next_if_eq(&c).is_none() {
+                    return Err(format!("expected `{}{}` in filter", c, c));
+                }
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Compare(Comparison::NotEqual),
+            '!' => Token::Not,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Compare(Comparison::Equal),
+            '=' => return Err("expected `==` in filter".to_string()),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Compare(Comparison::LessOrEqual),
+            '<' => Token::Compare(Comparison::Less),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Compare(Comparison::GreaterOrEqual),
+            '>' => Token::Compare(Comparison::Greater),
+            '"' => {
+                let mut word: String = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed `\"` in filter".to_string()),
+                    }
+                }
+                Token::Word(word)
+            }
+            c => {
+                let mut word: String = c.to_string();
+                while let Some(c) = chars.next_if(|c: &char| !c.is_whitespace() && !"()&|!=<>\"".contains(*c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => format!("`{}`", word),
+        Token::Compare(_) => "a comparison".to_string(),
+        Token::And => "`&&`".to_string(),
+        Token::Or => "`||`".to_string(),
+        Token::Not => "`!`".to_string(),
+        Token::Open => "`(`".to_string(),
+        Token::Close => "`)`".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::DateTime;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// A regular 2 KiB file named `main.rs`, modified at noon on 2024-03-15
+    fn entry() -> EntryAttributes<'static> {
+        EntryAttributes {
+            name: "main.rs",
+            ext: "rs",
+            size: 2048,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(1_710_504_000)),
+            is_dir: false,
+            hidden: false,
+        }
+    }
+
+    fn matches(filter: &str, entry: &EntryAttributes) -> bool {
+        Filter::parse(filter).unwrap().matches(entry)
+    }
+
+    fn error(filter: &str) -> String {
+        Filter::parse(filter).unwrap_err()
+    }
+
+    #[test]
+    fn entry_is_modified_at_noon() {
+        let modified: DateTime = DateTime::from_system_time(entry().modified.unwrap());
+        assert_eq!((modified.year, modified.month, modified.day, modified.hour), (2024, 3, 15, 12));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let hidden_dir: EntryAttributes = EntryAttributes { is_dir: true, hidden: true, ..entry() };
+        // is_dir || (hidden && ext==txt)
+        assert!(matches("is_dir || hidden && ext==txt", &hidden_dir));
+        assert!(!matches("(is_dir || hidden) && ext==txt", &hidden_dir));
+        // (ext==txt && hidden) || ext==rs
+        assert!(matches("ext==txt && hidden || ext==rs", &entry()));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_and_or() {
+        assert!(matches("!is_dir && ext==rs", &entry()));
+        assert!(!matches("!(is_dir || ext==rs)", &entry()));
+        assert!(matches("!is_dir || is_dir", &entry()));
+        assert!(matches("!!ext==rs", &entry()));
+    }
+
+    #[test]
+    fn parentheses_nest() {
+        assert!(matches("((ext==rs) && (size>1K || hidden))", &entry()));
+        assert!(!matches("!(ext==rs && !(hidden || size<1K))", &entry()));
+    }
+
+    #[test]
+    fn quoted_values_keep_spaces_and_operators() {
+        let spaced: EntryAttributes = EntryAttributes { name: "my file (1).rs", ..entry() };
+        assert!(matches("name==\"my file (1).rs\"", &spaced));
+        assert!(matches("name==\"my *\"", &spaced));
+        assert!(!matches("name==\"my *\"", &entry()));
+        assert!(matches("name == \"a&&b||c\" || ext==rs", &entry()));
+    }
+
+    #[test]
+    fn name_takes_a_glob_and_ext_ignores_case_and_dot() {
+        assert!(matches("name==*.rs", &entry()));
+        assert!(matches("name!=*.txt", &entry()));
+        assert!(matches("ext==.RS", &entry()));
+        assert!(!matches("ext!=rs", &entry()));
+    }
+
+    #[test]
+    fn size_compares_with_units() {
+        for (filter, expected) in [
+            ("size==2K", true),
+            ("size==2048", true),
+            ("size!=2KB", false),
+            ("size<2K", false),
+            ("size<=2k", true),
+            ("size>1.5K", true),
+            ("size>=2.5K", false),
+            ("size<1M", true),
+            ("size>0.001M", true),
+        ] {
+            assert_eq!(matches(filter, &entry()), expected, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn mtime_compares_with_the_period_a_date_names() {
+        for (filter, expected) in [
+            ("mtime==2024-03-15", true),
+            ("mtime!=2024-03-15", false),
+            ("mtime<2024-03-15", false),
+            ("mtime<=2024-03-15", true),
+            ("mtime>2024-03-14", true),
+            ("mtime>=2024-03-16", false),
+            ("mtime==2024-03-15T12:00", true),
+            ("mtime>2024-03-15T11:59:59", true),
+            ("mtime<2024-03-15T12:00:01", true),
+            ("mtime>2024-03-15T12:00", false),
+        ] {
+            assert_eq!(matches(filter, &entry()), expected, "{}", filter);
+        }
+        let unknown: EntryAttributes = EntryAttributes { modified: None, ..entry() };
+        assert!(!matches("mtime<2100-01-01", &unknown));
+        assert!(!matches("mtime>1970-01-01", &unknown));
+    }
+
+    #[test]
+    fn only_size_and_mtime_need_metadata() {
+        assert!(Filter::parse("name==a || size>1").unwrap().needs_metadata());
+        assert!(Filter::parse("!mtime<2024-01-01").unwrap().needs_metadata());
+        assert!(!Filter::parse("name==a && ext!=b || is_dir && !hidden").unwrap().needs_metadata());
+    }
+
+    #[test]
+    fn errors_name_what_went_wrong() {
+        for (filter, message) in [
+            ("name==\"main.rs", "unclosed `\"` in filter"),
+            ("(is_dir || hidden", "unclosed `(` in filter"),
+            ("(is_dir hidden)", "expected `)` in filter, found `hidden`"),
+            ("is_dir)", "unexpected `)` in filter"),
+            ("is_dir &", "expected `&&` in filter"),
+            ("is_dir | hidden", "expected `||` in filter"),
+            ("name=main.rs", "expected `==` in filter"),
+            ("is_dir &&", "filter ends where a condition was expected"),
+            ("&& is_dir", "expected a condition in filter, found `&&`"),
+            ("owner==root", "unknown filter attribute `owner`, expected name, ext, size, mtime, is_dir or hidden"),
+            ("size", "expected a comparison after `size` in filter"),
+            ("size>", "expected a value after `size` comparison in filter"),
+            ("name<main.rs", "`name` can only be compared with == or != in filter"),
+            ("size>10Q", "invalid size `10Q`, expected e.g. 512, 10K, 1.5M or 2G"),
+        ] {
+            assert_eq!(error(filter), message, "{}", filter);
+        }
+        assert!(error("mtime>yesterday").contains("yesterday"));
+    }
+}
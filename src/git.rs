@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Working tree state of a file as reported by `git status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitStatus {
+    Untracked,
+    Modified,
+    Staged,
+}
+
+impl GitStatus {
+    /// ANSI color used by `--color-by-git-status`
+    pub fn color(self) -> &'static str {
+        match self {
+            GitStatus::Untracked => "31",
+            GitStatus::Modified => "33",
+            GitStatus::Staged => "32",
+        }
+    }
+
+    /// Name shown for the status by `--legend`
+    pub fn label(self) -> &'static str {
+        match self {
+            GitStatus::Untracked => "untracked",
+            GitStatus::Modified => "modified",
+            GitStatus::Staged => "staged",
+        }
+    }
+}
+
+/// Runs `git status` for the repository containing `dir` and maps each
+/// changed file's canonical path to its status. Directories outside a
+/// repository, or a missing `git` binary, yield an empty map.
+pub fn status_map(dir: &Path) -> HashMap<PathBuf, GitStatus> {
+    let mut statuses: HashMap<PathBuf, GitStatus> = HashMap::new();
+
+    let Some(toplevel) = run_git(dir, &["rev-parse", "--show-toplevel"]) else {
+        return statuses;
+    };
+    let toplevel: PathBuf = PathBuf::from(toplevel.trim_end());
+    let toplevel: PathBuf = toplevel.canonicalize().unwrap_or(toplevel);
+
+    let Some(output) = run_git(dir, &["status", "--porcelain=v1", "-z", "--untracked-files=all"]) else {
+        return statuses;
+    };
+
+    let mut records = output.split('\0');
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+
+        let (code, path) = record.split_at(3);
+        let mut code_chars = code.chars();
+        let index: char = code_chars.next().unwrap_or(' ');
+        let worktree: char = code_chars.next().unwrap_or(' ');
+
+        // Renames and copies carry the original path as an extra record
+        if index == 'R' || index == 'C' {
+            records.next();
+        }
+
+        // Unstaged changes take precedence over staged ones
+        let status: GitStatus = if index == '?' {
+            GitStatus::Untracked
+        } else if worktree != ' ' {
+            GitStatus::Modified
+        } else {
+            GitStatus::Staged
+        };
+
+        statuses.insert(toplevel.join(path), status);
+    }
+
+    statuses
+}
+
+fn run_git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
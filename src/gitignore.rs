@@ -1,63 +1,269 @@
+use crate::pattern;
 use std::fs;
 use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub struct GitignoreManager {
+/// The patterns of one `.gitignore`, anchored at its directory
+struct PatternSet {
+    /// Components from the repository root to the `.gitignore` directory
+    anchor: Vec<String>,
+    /// The `.gitignore` file the patterns were read from
+    source: PathBuf,
     patterns: Vec<String>,
+    /// Match with full gitignore glob syntax (`--strict-gitignore`)
+    strict: bool,
+}
+
+/// The pattern that decided about an entry, as reported by --explain-ignore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IgnoreMatch<'a> {
+    /// `true` if the entry is ignored, `false` if a negation re-includes it
+    pub ignored: bool,
+    pub pattern: &'a str,
+    pub source: &'a Path,
+}
+
+pub struct GitignoreManager {
+    /// Pattern sets from the repository root down to the traversal root,
+    /// followed in strict mode by those found during the traversal
+    sets: Vec<PatternSet>,
+    /// Components from the repository root to the traversal root
+    root_prefix: Vec<String>,
+    /// The traversal root, as given
+    root: PathBuf,
+    /// Follow git's own semantics: read nested `.gitignore` files and
+    /// `.git/info/exclude`, support `**` and classes, and never re-include
+    /// anything below an ignored directory
+    strict: bool,
+}
+
+/// Walks up from `dir` to the nearest directory containing `.git`
+pub fn find_repo_root(dir: &Path) -> Option<PathBuf> {
+    let dir: PathBuf = dir.canonicalize().ok()?;
+    dir.ancestors()
+        .find(|ancestor: &&Path| ancestor.join(".git").exists())
+        .map(Path::to_path_buf)
 }
 
 impl GitignoreManager {
-    pub fn new() -> Self {
+    pub fn new(strict: bool) -> Self {
         Self {
-            patterns: Vec::new(),
+            sets: Vec::new(),
+            root_prefix: Vec::new(),
+            root: PathBuf::new(),
+            strict,
         }
     }
 
-    pub fn load_patterns(&mut self, dir: &Path) {
-        self.patterns = self.read_gitignore(dir);
+    /// Loads the `.gitignore` files that apply to a traversal of `dir`. With a
+    /// repository root, every `.gitignore` from that root down to `dir` is
+    /// read and anchored at its own directory, so `/`-anchored patterns keep
+    /// their meaning when listing a subdirectory.
+    pub fn load_patterns(&mut self, dir: &Path, repo_root: Option<&Path>) {
+        self.sets.clear();
+        self.root_prefix.clear();
+        self.root = dir.to_path_buf();
+
+        let canonical_dir: Option<PathBuf> = dir.canonicalize().ok();
+        let relative_dir: Option<&Path> = match (repo_root, &canonical_dir) {
+            (Some(repo_root), Some(canonical_dir)) => canonical_dir.strip_prefix(repo_root).ok(),
+            _ => None,
+        };
+
+        let (Some(repo_root), Some(relative_dir)) = (repo_root, relative_dir) else {
+            // Outside a repository only the traversal root's file applies
+            self.push_set(dir, Vec::new());
+            return;
+        };
+
+        self.root_prefix = path_components(relative_dir);
+        if self.strict {
+            // Repository-local excludes rank below every `.gitignore`
+            self.push_file(repo_root.join(".git").join("info").join("exclude"), Vec::new());
+        }
+        let mut current: PathBuf = repo_root.to_path_buf();
+        self.push_set(&current, Vec::new());
+        for (index, component) in self.root_prefix.clone().iter().enumerate() {
+            current.push(component);
+            self.push_set(&current, self.root_prefix[..=index].to_vec());
+        }
     }
 
-    pub fn matches(&self, path: &Path, base_dir: &Path) -> bool {
-        if self.patterns.is_empty() {
-            return false;
+    /// Reads the `.gitignore` of a directory below the traversal root as the
+    /// traversal reaches it, once; only strict mode looks at nested files
+    pub fn enter_dir(&mut self, dir: &Path) {
+        if !self.strict {
+            return;
+        }
+        let relative: Option<&Path> = dir.strip_prefix(&self.root).ok();
+        let Some(relative) = relative.filter(|relative: &&Path| !relative.as_os_str().is_empty()) else {
+            return;
+        };
+        let mut anchor: Vec<String> = self.root_prefix.clone();
+        anchor.extend(path_components(relative));
+        if !self.sets.iter().any(|set: &PatternSet| set.anchor == anchor) {
+            self.push_set(dir, anchor);
         }
+    }
 
-        let filename = path
-            .file_name()
-            .map(|n| n.to_string_lossy().to_string())
-            .unwrap_or_default();
+    pub fn matches(&self, path: &Path, base_dir: &Path, is_dir: bool) -> bool {
+        self.decision(path, base_dir, is_dir).is_some_and(|decision: IgnoreMatch| decision.ignored)
+    }
+
+    /// Returns the pattern deciding about the entry, either ignoring it or
+    /// re-including it by a negation, or `None` if no pattern applies to it
+    /// or any of its parents. `is_dir` comes from the caller, which already
+    /// knows it from the directory listing, so matching never needs a stat.
+    pub fn decision(&self, path: &Path, base_dir: &Path, is_dir: bool) -> Option<IgnoreMatch<'_>> {
+        if self.sets.is_empty() {
+            return None;
+        }
 
         // Relative path from the base directory
-        let relative_path = path
-            .strip_prefix(base_dir)
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_default();
+        let relative_path = path.strip_prefix(base_dir).ok()?;
 
-        self.patterns
-            .iter()
-            .any(|pattern: &String| self.matches_pattern(&filename, &relative_path, pattern, path.is_dir()))
+        // Entries are matched by their path from the repository root
+        let mut components: Vec<String> = self.root_prefix.clone();
+        components.extend(path_components(relative_path));
+
+        // Walk from the repository root down to the entry itself. A component
+        // without any matching pattern inherits the state of its parent, so
+        // everything below an ignored directory stays ignored unless a
+        // negation re-includes it. A deeper `.gitignore` takes precedence
+        // over the ones above it.
+        let mut ignored: Option<IgnoreMatch> = None;
+        let entry_is_dir: bool = is_dir;
+        for index in 0..components.len() {
+            let filename: &str = &components[index];
+            let is_dir = index + 1 < components.len() || entry_is_dir;
+
+            let decision: Option<IgnoreMatch> = self.sets.iter().rev().find_map(|set: &PatternSet| {
+                // A `.gitignore` never applies to its own directory or above
+                let relative: &[String] = components[..=index]
+                    .strip_prefix(set.anchor.as_slice())
+                    .filter(|relative: &&[String]| !relative.is_empty())?;
+                let (ignored, pattern) = set.last_match(filename, &relative.join("/"), is_dir)?;
+                Some(IgnoreMatch {
+                    ignored,
+                    pattern,
+                    source: &set.source,
+                })
+            });
+            if decision.is_some() {
+                ignored = decision;
+            }
+
+            // Git never looks inside an ignored directory, so in strict mode
+            // nothing below it can be re-included
+            if self.strict && is_dir && ignored.is_some_and(|decision: IgnoreMatch| decision.ignored) {
+                return ignored;
+            }
+        }
+
+        // An ignored directory is still traversed when a negated pattern
+        // re-includes something beneath it (e.g. `build/` + `!build/keep/`).
+        if !self.strict && ignored.is_some_and(|decision: IgnoreMatch| decision.ignored) && entry_is_dir {
+            let negation: Option<IgnoreMatch> = self.sets.iter().find_map(|set: &PatternSet| {
+                let relative: &[String] = components.strip_prefix(set.anchor.as_slice())?;
+                let pattern: &str = set.negated_descendant(&relative.join("/"))?;
+                Some(IgnoreMatch {
+                    ignored: false,
+                    pattern,
+                    source: &set.source,
+                })
+            });
+            if negation.is_some() {
+                return negation;
+            }
+        }
+
+        ignored
     }
 
-    fn read_gitignore(&self, dir: &Path) -> Vec<String> {
-        let gitignore_path: std::path::PathBuf = dir.join(".gitignore");
-        if !gitignore_path.exists() {
-            return Vec::new();
+    fn push_set(&mut self, dir: &Path, anchor: Vec<String>) {
+        self.push_file(dir.join(".gitignore"), anchor);
+    }
+
+    fn push_file(&mut self, source: PathBuf, anchor: Vec<String>) {
+        let patterns: Vec<String> = read_gitignore(&source, self.strict);
+        if !patterns.is_empty() {
+            self.sets.push(PatternSet {
+                anchor,
+                source,
+                patterns,
+                strict: self.strict,
+            });
         }
+    }
+}
 
-        let file: fs::File = match fs::File::open(&gitignore_path) {
-            Ok(file) => file,
-            Err(_) => return Vec::new(),
-        };
+fn path_components(path: &Path) -> Vec<String> {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect()
+}
 
-        let reader: BufReader<fs::File> = BufReader::new(file);
-        reader
-            .lines()
-            .filter_map(Result::ok)
-            .filter(|line| {
-                // Skip comments and empty lines
-                !line.trim().is_empty() && !line.trim().starts_with('#')
+fn read_gitignore(gitignore_path: &Path, strict: bool) -> Vec<String> {
+    if !gitignore_path.exists() {
+        return Vec::new();
+    }
+
+    let file: fs::File = match fs::File::open(gitignore_path) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+
+    let reader: BufReader<fs::File> = BufReader::new(file);
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .map(|line: String| if strict { trim_unescaped_end(&line).to_string() } else { line })
+        .filter(|line| {
+            // Skip comments and empty lines
+            !line.trim().is_empty() && !line.trim().starts_with('#')
+        })
+        .collect()
+}
+
+/// Drops trailing spaces unless the last one is escaped with `\`, as git does
+fn trim_unescaped_end(line: &str) -> &str {
+    let trimmed: &str = line.trim_end_matches(' ');
+    if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        &line[..trimmed.len() + 1]
+    } else {
+        trimmed
+    }
+}
+
+impl PatternSet {
+    /// Returns the decision of the last pattern matching the entry, following
+    /// gitignore's "last match wins" rule, along with that pattern: `true` if
+    /// ignored, `false` if re-included by a negation, `None` if nothing matched.
+    fn last_match(&self, filename: &str, relative_path: &str, is_dir: bool) -> Option<(bool, &str)> {
+        self.patterns.iter().rev().find_map(|original: &String| {
+            let is_negation = original.starts_with('!');
+            let pattern = if is_negation { &original[1..] } else { original.as_str() };
+
+            if self.matches_pattern(filename, relative_path, pattern, is_dir) {
+                Some((!is_negation, original.as_str()))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns a negation re-including something strictly below the directory
+    fn negated_descendant(&self, relative_dir: &str) -> Option<&str> {
+        let dir_prefix = format!("{}/", relative_dir);
+        self.patterns
+            .iter()
+            .find(|pattern: &&String| {
+                pattern
+                    .strip_prefix('!')
+                    .map(|p| p.trim_start_matches('/'))
+                    .is_some_and(|p| p.starts_with(&dir_prefix) && p.trim_end_matches('/') != relative_dir)
             })
-            .collect()
+            .map(String::as_str)
     }
 
     fn matches_pattern(
@@ -67,79 +273,194 @@ impl GitignoreManager {
         pattern: &str,
         is_dir: bool,
     ) -> bool {
-        // Handle negation patterns
-        let is_negation = pattern.starts_with('!');
-        let pattern = if is_negation { &pattern[1..] } else { pattern };
-
-        // Handle absolute path patterns starting with /
-        let is_absolute_pattern = pattern.starts_with('/');
-        let pattern = pattern.trim_start_matches('/');
-
         // Handle directory-only patterns ending with /
         let is_directory_pattern = pattern.ends_with('/');
         let pattern = pattern.trim_end_matches('/');
 
-        // Check exact filename or path matches
+        if is_directory_pattern && !is_dir {
+            return false;
+        }
+
+        // Patterns starting with / or containing a / are anchored to the
+        // gitignore location and matched against the relative path
+        let is_absolute_pattern = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+
         if is_absolute_pattern {
-            // For absolute patterns, match against relative path
-            if relative_path == pattern {
-                return !is_negation;
-            }
+            self.matches_filename_pattern(relative_path, pattern)
         } else {
-            // For relative patterns, match filename or path
-            if is_directory_pattern && is_dir {
-                if filename == pattern || relative_path.contains(&format!("/{}/", pattern)) {
-                    return !is_negation;
-                }
-            } else {
-                // Wildcard matching for filename
-                if self.matches_filename_pattern(filename, pattern)
-                    || relative_path.contains(&format!("/{}", pattern))
-                {
-                    return !is_negation;
-                }
-            }
+            self.matches_filename_pattern(filename, pattern)
         }
-
-        false
     }
 
     fn matches_filename_pattern(&self, filename: &str, pattern: &str) -> bool {
-        let pattern_chars: Vec<char> = pattern.chars().collect();
-        let filename_chars: Vec<char> = filename.chars().collect();
-
-        fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
-            fn match_helper(p: &[char], t: &[char], p_idx: usize, t_idx: usize) -> bool {
-                // Base cases
-                if p_idx == p.len() {
-                    return t_idx == t.len();
-                }
-
-                if t_idx == t.len() {
-                    // Only * can match empty string at end
-                    return p[p_idx..].iter().all(|&c| c == '*');
-                }
-
-                // Wildcard handling
-                match p[p_idx] {
-                    '*' => {
-                        // Try matching 0 or more characters
-                        (t_idx..=t.len()).any(|i| match_helper(p, t, p_idx + 1, i))
-                    }
-                    '?' => {
-                        // Match any single character
-                        match_helper(p, t, p_idx + 1, t_idx + 1)
-                    }
-                    c => {
-                        // Exact character match
-                        c == t[t_idx] && match_helper(p, t, p_idx + 1, t_idx + 1)
-                    }
-                }
-            }
+        if self.strict {
+            pattern::wildmatch(pattern, filename)
+        } else {
+            pattern::glob_match(pattern, filename)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    /// A repository with `gitignore` at its root
+    fn repo(gitignore: &str) -> TempDir {
+        let repo: TempDir = TempDir::new("gitignore");
+        repo.dir(".git");
+        repo.write(".gitignore", gitignore);
+        repo
+    }
+
+    #[test]
+    fn negated_directory_re_includes_its_contents() {
+        let repo: TempDir = repo("build/\n!build/keep/\n");
+        repo.file("build/keep/a");
+        repo.file("build/other");
+
+        let mut manager: GitignoreManager = GitignoreManager::new(false);
+        manager.load_patterns(repo.path(), find_repo_root(repo.path()).as_deref());
+
+        // `build/` itself is still walked to reach `keep/`
+        assert!(!manager.matches(&repo.join("build"), repo.path(), true));
+        assert!(!manager.matches(&repo.join("build/keep"), repo.path(), true));
+        assert!(!manager.matches(&repo.join("build/keep/a"), repo.path(), false));
+        assert!(manager.matches(&repo.join("build/other"), repo.path(), false));
+    }
+
+    #[test]
+    fn negated_directory_applies_when_listing_a_subdirectory() {
+        let repo: TempDir = repo("build/\n!build/keep/\n");
+        repo.file("build/keep/a");
+        repo.file("build/other");
+
+        let listed: PathBuf = repo.join("build");
+        let mut manager: GitignoreManager = GitignoreManager::new(false);
+        manager.load_patterns(&listed, find_repo_root(&listed).as_deref());
 
-            match_helper(pattern, text, 0, 0)
+        assert!(!manager.matches(&repo.join("build/keep"), &listed, true));
+        assert!(!manager.matches(&repo.join("build/keep/a"), &listed, false));
+        assert!(manager.matches(&repo.join("build/other"), &listed, false));
+    }
+
+    #[test]
+    fn trailing_spaces_are_trimmed_unless_escaped() {
+        assert_eq!(trim_unescaped_end("foo"), "foo");
+        assert_eq!(trim_unescaped_end("foo   "), "foo");
+        assert_eq!(trim_unescaped_end("foo\\ "), "foo\\ ");
+        assert_eq!(trim_unescaped_end("foo\\    "), "foo\\ ");
+        // A backslash without a following space is kept as written
+        assert_eq!(trim_unescaped_end("foo\\"), "foo\\");
+        assert_eq!(trim_unescaped_end("   "), "");
+    }
+
+    #[test]
+    fn strict_mode_keeps_escaped_trailing_space() {
+        let repo: TempDir = repo("name\\ \nplain   \n");
+        repo.file("name ");
+        repo.file("name");
+        repo.file("plain");
+
+        let mut manager: GitignoreManager = GitignoreManager::new(true);
+        manager.load_patterns(repo.path(), find_repo_root(repo.path()).as_deref());
+
+        assert!(manager.matches(&repo.join("name "), repo.path(), false));
+        assert!(!manager.matches(&repo.join("name"), repo.path(), false));
+        assert!(manager.matches(&repo.join("plain"), repo.path(), false));
+    }
+
+    #[test]
+    fn strict_mode_never_re_includes_below_an_ignored_directory() {
+        let repo: TempDir = repo("build/\n!build/keep/\n!build/keep/a\n");
+        repo.file("build/keep/a");
+
+        let mut manager: GitignoreManager = GitignoreManager::new(true);
+        manager.load_patterns(repo.path(), find_repo_root(repo.path()).as_deref());
+
+        assert!(manager.matches(&repo.join("build"), repo.path(), true));
+        assert!(manager.matches(&repo.join("build/keep"), repo.path(), true));
+        assert!(manager.matches(&repo.join("build/keep/a"), repo.path(), false));
+    }
+
+    #[test]
+    fn strict_mode_re_includes_files_below_a_directory_glob() {
+        // `build/*` ignores the entries, not the directory, so git still
+        // looks inside and the negation applies
+        let repo: TempDir = repo("build/*\n!build/keep\n");
+        repo.file("build/keep");
+        repo.file("build/other");
+
+        let mut manager: GitignoreManager = GitignoreManager::new(true);
+        manager.load_patterns(repo.path(), find_repo_root(repo.path()).as_deref());
+
+        assert!(!manager.matches(&repo.join("build"), repo.path(), true));
+        assert!(!manager.matches(&repo.join("build/keep"), repo.path(), true));
+        assert!(manager.matches(&repo.join("build/other"), repo.path(), false));
+    }
+
+    #[test]
+    fn last_matching_pattern_wins_within_a_file() {
+        let repo: TempDir = repo("*.log\n!keep.log\nkeep.log\n!debug.log\n");
+        repo.file("keep.log");
+        repo.file("debug.log");
+        repo.file("other.log");
+
+        let mut manager: GitignoreManager = GitignoreManager::new(false);
+        manager.load_patterns(repo.path(), find_repo_root(repo.path()).as_deref());
+
+        assert!(manager.matches(&repo.join("keep.log"), repo.path(), false));
+        assert!(!manager.matches(&repo.join("debug.log"), repo.path(), false));
+        assert!(manager.matches(&repo.join("other.log"), repo.path(), false));
+        let decision: IgnoreMatch = manager.decision(&repo.join("keep.log"), repo.path(), false).unwrap();
+        assert_eq!(decision.pattern, "keep.log");
+    }
+
+    #[test]
+    fn deeper_gitignore_beats_a_shallower_one() {
+        let repo: TempDir = repo("*.tmp\n!*.bak\n");
+        repo.write("sub/.gitignore", "!keep.tmp\n*.bak\n");
+        repo.file("sub/keep.tmp");
+        repo.file("sub/other.tmp");
+        repo.file("sub/old.bak");
+        repo.file("old.bak");
+        repo.file("keep.tmp");
+
+        for strict in [false, true] {
+            let listed: PathBuf = repo.join("sub");
+            let mut manager: GitignoreManager = GitignoreManager::new(strict);
+            manager.load_patterns(&listed, find_repo_root(&listed).as_deref());
+
+            assert!(!manager.matches(&repo.join("sub/keep.tmp"), &listed, false), "strict: {}", strict);
+            assert!(manager.matches(&repo.join("sub/other.tmp"), &listed, false), "strict: {}", strict);
+            assert!(manager.matches(&repo.join("sub/old.bak"), &listed, false), "strict: {}", strict);
+            let decision: IgnoreMatch = manager.decision(&repo.join("sub/old.bak"), &listed, false).unwrap();
+            assert_eq!(decision.source, repo.join("sub/.gitignore"));
         }
 
-        wildcard_match(&pattern_chars, &filename_chars)
+        // From the repository root, strict mode reads the nested file as the
+        // traversal enters `sub`; the root's rules still decide above it
+        let mut manager: GitignoreManager = GitignoreManager::new(true);
+        manager.load_patterns(repo.path(), find_repo_root(repo.path()).as_deref());
+        manager.enter_dir(&repo.join("sub"));
+        assert!(manager.matches(&repo.join("keep.tmp"), repo.path(), false));
+        assert!(!manager.matches(&repo.join("old.bak"), repo.path(), false));
+        assert!(!manager.matches(&repo.join("sub/keep.tmp"), repo.path(), false));
+        assert!(manager.matches(&repo.join("sub/old.bak"), repo.path(), false));
+    }
+
+    #[test]
+    fn directory_patterns_use_the_callers_entry_type() {
+        let repo: TempDir = repo("cache/\n");
+
+        let mut manager: GitignoreManager = GitignoreManager::new(false);
+        manager.load_patterns(repo.path(), find_repo_root(repo.path()).as_deref());
+
+        // Nothing exists on disk, so only `is_dir` can tell them apart
+        assert!(manager.matches(&repo.join("cache"), repo.path(), true));
+        assert!(!manager.matches(&repo.join("cache"), repo.path(), false));
+        assert!(manager.matches(&repo.join("cache/entry"), repo.path(), false));
     }
 }
@@ -0,0 +1,175 @@
+use crate::config::Charset;
+use crate::pattern;
+use std::fs;
+use std::path::Path;
+
+/// Name of the annotation file read from each root by `--info`
+pub const INFO_FILE: &str = ".tree.info";
+
+/// Comments attached to entries by a `.tree.info` file. Each block starts
+/// with one or more pattern lines followed by tab-indented comment lines;
+/// lines starting with `#` are ignored.
+///
+/// ```text
+/// src/
+/// *.toml
+///     Sources and manifests (indented with a tab)
+/// ```
+pub struct InfoFile {
+    entries: Vec<InfoEntry>,
+}
+
+struct InfoEntry {
+    patterns: Vec<String>,
+    comment: Vec<String>,
+}
+
+impl InfoFile {
+    /// Reads `dir/.tree.info`; a missing or unreadable file has no entries
+    pub fn load(dir: &Path) -> Self {
+        let contents: String = fs::read_to_string(dir.join(INFO_FILE)).unwrap_or_default();
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut entries: Vec<InfoEntry> = Vec::new();
+        let mut patterns: Vec<String> = Vec::new();
+        let mut comment: Vec<String> = Vec::new();
+
+        for line in contents.lines() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(text) = line.strip_prefix('\t') {
+                comment.push(text.trim_end().to_string());
+                continue;
+            }
+
+            // A pattern after comment lines starts the next block
+            if !comment.is_empty() {
+                entries.push(InfoEntry {
+                    patterns: std::mem::take(&mut patterns),
+                    comment: std::mem::take(&mut comment),
+                });
+            }
+            patterns.push(line.trim().to_string());
+        }
+
+        if !comment.is_empty() {
+            entries.push(InfoEntry { patterns, comment });
+        }
+
+        Self { entries }
+    }
+
+    /// Returns the comment of the first block matching the entry. Patterns
+    /// with a `/` inside are matched against the path relative to the root,
+    /// others against the name; a trailing `/` only matches directories.
+    pub fn comment_for(&self, relative_path: &str, name: &str, is_dir: bool) -> Option<&[String]> {
+        self.entries
+            .iter()
+            .find(|entry: &&InfoEntry| {
+                entry.patterns.iter().any(|pattern: &String| {
+                    let is_directory_pattern: bool = pattern.ends_with('/');
+                    let pattern: &str = pattern.trim_end_matches('/');
+                    if is_directory_pattern && !is_dir {
+                        return false;
+                    }
+
+                    if pattern.contains('/') {
+                        pattern::glob_match(pattern.trim_start_matches('/'), relative_path)
+                    } else {
+                        pattern::glob_match(pattern, name)
+                    }
+                })
+            })
+            .map(|entry: &InfoEntry| entry.comment.as_slice())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Frames a comment in braces, wrapping each comment line at word breaks so
+/// the framed lines fit in `width` columns. A word longer than that gets a
+/// line of its own rather than being split.
+pub fn comment_block(comment: &[String], charset: Charset, width: usize) -> Vec<String> {
+    let (single, first, middle, last): (&str, &str, &str, &str) = match charset {
+        Charset::Utf8 | Charset::Auto => ("{", "⎧", "⎪", "⎩"),
+        Charset::Ascii => ("{", "{", "|", "}"),
+    };
+    // The brace and the space after it
+    let text_width: usize = width.saturating_sub(2).max(1);
+    let lines: Vec<String> = comment.iter().flat_map(|line: &String| wrap(line, text_width)).collect();
+
+    if lines.len() == 1 {
+        return vec![format!("{} {}", single, lines[0])];
+    }
+
+    let end: usize = lines.len() - 1;
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let brace: &str = match index {
+                0 => first,
+                _ if index == end => last,
+                _ => middle,
+            };
+            format!("{} {}", brace, line)
+        })
+        .collect()
+}
+
+/// Splits a line into lines of at most `width` characters at spaces
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![String::new()];
+    for word in line.split_whitespace() {
+        let current: &mut String = lines.last_mut().expect("there is always a current line");
+        if current.is_empty() {
+            current.push_str(word);
+        } else if current.chars().count() + 1 + word.chars().count() <= width {
+            current.push(' ');
+            current.push_str(word);
+        } else {
+            lines.push(word.to_string());
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(comment: &[&str]) -> Vec<String> {
+        comment.iter().map(|line: &&str| line.to_string()).collect()
+    }
+
+    #[test]
+    fn ascii_blocks_use_ascii_braces() {
+        let block: Vec<String> = comment_block(&lines(&["one", "two", "three"]), Charset::Ascii, 80);
+        assert_eq!(block, ["{ one", "| two", "} three"]);
+        assert!(block.iter().all(|line: &String| line.is_ascii()));
+        assert_eq!(comment_block(&lines(&["only"]), Charset::Ascii, 80), ["{ only"]);
+    }
+
+    #[test]
+    fn utf8_blocks_use_curly_brackets() {
+        let block: Vec<String> = comment_block(&lines(&["one", "two", "three"]), Charset::Utf8, 80);
+        assert_eq!(block, ["⎧ one", "⎪ two", "⎩ three"]);
+    }
+
+    #[test]
+    fn long_lines_wrap_to_the_width() {
+        let block: Vec<String> = comment_block(&lines(&["sources of the command line tool"]), Charset::Ascii, 16);
+        assert_eq!(block, ["{ sources of the", "| command line", "} tool"]);
+        assert!(block.iter().all(|line: &String| line.chars().count() <= 16));
+
+        // A word longer than the width is not split
+        let block: Vec<String> = comment_block(&lines(&["a unbreakable_word"]), Charset::Ascii, 8);
+        assert_eq!(block, ["{ a", "} unbreakable_word"]);
+    }
+}
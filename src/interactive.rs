@@ -0,0 +1,441 @@
+use crate::config::{Charset, Config};
+use crate::error::TreeResult;
+use crate::node::TreeNode;
+use crate::terminal::{self, stty};
+use crate::tree::TreeGenerator;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+/// Key bindings shown on the last line of the screen
+const HELP: &str = "up/down move  right/enter expand  left collapse  space toggle  q quit";
+
+/// One line of the browser
+struct Row {
+    node: TreeNode,
+    /// The root the entry was listed under
+    root: PathBuf,
+    /// Directories between the root and the entry; roots are at depth 0
+    depth: usize,
+    expanded: bool,
+}
+
+enum Key {
+    Up,
+    Down,
+    Expand,
+    Collapse,
+    Toggle,
+    Quit,
+    Other,
+}
+
+/// Runs the --interactive browser on the terminal until `q`. Directories
+/// start collapsed and are read when first expanded. Everything is drawn on
+/// the terminal itself, so nothing reaches stdout.
+pub fn run(generator: &mut TreeGenerator, config: &Config) -> TreeResult<()> {
+    if !cfg!(unix) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "--interactive needs a Unix terminal").into());
+    }
+
+    let mut tty: fs::File = fs::OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let height: usize = terminal_height();
+    let _guard: TerminalGuard<fs::File> = TerminalGuard::enter(tty.try_clone()?, stty)?;
+    let _signals: signals::Caught = signals::catch();
+    browse(generator, config, &mut tty, height)
+}
+
+/// Alternate screen, hidden cursor and no line wrapping while browsing
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l\x1b[?7l";
+
+/// Undoes `ENTER_SCREEN` in reverse order
+const LEAVE_SCREEN: &str = "\x1b[?7h\x1b[?25h\x1b[?1049l";
+
+/// Keeps the terminal set up for browsing and puts it back as it was when
+/// dropped, so an error or a panic never leaves it raw or on the alternate
+/// screen. Setting up and restoring mirror each other: the screen is left
+/// while output processing is still off, then the saved modes come back.
+struct TerminalGuard<W: Write> {
+    tty: W,
+    saved: String,
+    stty: fn(&[&str]) -> io::Result<String>,
+}
+
+impl<W: Write> TerminalGuard<W> {
+    fn enter(tty: W, stty: fn(&[&str]) -> io::Result<String>) -> io::Result<Self> {
+        let saved: String = stty(&["-g"])?;
+        // Reads give up after a tenth of a second, so the browser notices
+        // signals while waiting for a key
+        stty(&["raw", "-echo", "min", "0", "time", "1"])?;
+        let mut guard: TerminalGuard<W> = TerminalGuard { tty, saved, stty };
+        guard.tty.write_all(ENTER_SCREEN.as_bytes())?;
+        guard.tty.flush()?;
+        Ok(guard)
+    }
+}
+
+impl<W: Write> Drop for TerminalGuard<W> {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(LEAVE_SCREEN.as_bytes());
+        let _ = self.tty.flush();
+        let _ = (self.stty)(&[self.saved.trim()]);
+    }
+}
+
+fn browse(generator: &mut TreeGenerator, config: &Config, tty: &mut fs::File, height: usize) -> TreeResult<()> {
+    let mut rows: Vec<Row> = config
+        .paths
+        .iter()
+        .map(|root: &PathBuf| Row {
+            node: TreeNode::new(root.display().to_string(), root.clone(), true),
+            root: root.clone(),
+            depth: 0,
+            expanded: false,
+        })
+        .collect();
+    let mut cursor: usize = 0;
+    let mut top: usize = 0;
+    // One line stays free for the key bindings
+    let visible: usize = height.saturating_sub(1).max(1);
+
+    loop {
+        if cursor < top {
+            top = cursor;
+        } else if cursor >= top + visible {
+            top = cursor + 1 - visible;
+        }
+        draw(generator, tty, &rows, cursor, top, visible)?;
+
+        let key: Key = loop {
+            if signals::interrupted() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted").into());
+            }
+            if let Some(key) = read_key(tty)? {
+                break key;
+            }
+        };
+        match key {
+            Key::Up => cursor = cursor.saturating_sub(1),
+            Key::Down => cursor = (cursor + 1).min(rows.len() - 1),
+            Key::Expand => expand(generator, &mut rows, cursor)?,
+            Key::Collapse if rows[cursor].expanded => collapse(&mut rows, cursor),
+            // On a collapsed entry, Left moves to its parent
+            Key::Collapse => {
+                let depth: usize = rows[cursor].depth;
+                if let Some(parent) = rows[..cursor].iter().rposition(|row: &Row| row.depth < depth) {
+                    cursor = parent;
+                }
+            }
+            Key::Toggle if rows[cursor].expanded => collapse(&mut rows, cursor),
+            Key::Toggle => expand(generator, &mut rows, cursor)?,
+            Key::Quit => return Ok(()),
+            Key::Other => {}
+        }
+    }
+}
+
+/// Lists a directory's entries below its row; they are read again on every
+/// expansion, so the view picks up changes
+fn expand(generator: &mut TreeGenerator, rows: &mut Vec<Row>, index: usize) -> TreeResult<()> {
+    let row: &Row = &rows[index];
+    if !expandable(generator, row) || row.expanded {
+        return Ok(());
+    }
+
+    let root: PathBuf = row.root.clone();
+    let depth: usize = row.depth;
+    let children: Vec<TreeNode> = generator.list_children(&root, &row.node.path.clone(), depth)?;
+    let children: Vec<Row> = children
+        .into_iter()
+        .map(|node: TreeNode| Row {
+            node,
+            root: root.clone(),
+            depth: depth + 1,
+            expanded: false,
+        })
+        .collect();
+    rows.splice(index + 1..index + 1, children);
+    rows[index].expanded = true;
+    Ok(())
+}
+
+/// Whether a row is a directory within the -L limit of its root
+fn expandable(generator: &TreeGenerator, row: &Row) -> bool {
+    row.node.is_dir && generator.lists_level(&row.root, row.depth)
+}
+
+/// Removes the rows beneath an expanded directory
+fn collapse(rows: &mut Vec<Row>, index: usize) {
+    let depth: usize = rows[index].depth;
+    let end: usize = rows[index + 1..]
+        .iter()
+        .position(|row: &Row| row.depth <= depth)
+        .map_or(rows.len(), |offset: usize| index + 1 + offset);
+    rows.drain(index + 1..end);
+    rows[index].expanded = false;
+}
+
+fn draw(
+    generator: &TreeGenerator,
+    tty: &mut fs::File,
+    rows: &[Row],
+    cursor: usize,
+    top: usize,
+    visible: usize,
+) -> io::Result<()> {
+    let (expanded_marker, collapsed_marker): (&str, &str) = match generator.charset() {
+        Charset::Ascii => ("- ", "+ "),
+        Charset::Utf8 | Charset::Auto => ("▾ ", "▸ "),
+    };
+
+    let mut screen: String = String::from("\x1b[H\x1b[2J");
+    for (index, row) in rows.iter().enumerate().skip(top).take(visible) {
+        // Directories at the -L limit are drawn like files
+        let marker: &str = match (expandable(generator, row), row.expanded) {
+            (false, _) => "  ",
+            (true, true) => expanded_marker,
+            (true, false) => collapsed_marker,
+        };
+        let name: String = if row.depth == 0 {
+            row.node.name.clone()
+        } else {
+            generator.display_name(&row.node)
+        };
+        let line: String = format!("{}{}{}", "  ".repeat(row.depth), marker, name);
+        if index == cursor {
+            screen.push_str(&format!("\x1b[7m{}\x1b[0m\r\n", line));
+        } else {
+            screen.push_str(&format!("{}\r\n", line));
+        }
+    }
+    screen.push_str(&format!("\x1b[{};1H\x1b[2m{}\x1b[0m", visible + 1, HELP));
+
+    tty.write_all(screen.as_bytes())?;
+    tty.flush()
+}
+
+/// Waits briefly for a key, returning `None` if none was pressed
+fn read_key(tty: &mut fs::File) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(tty)? else {
+        return Ok(None);
+    };
+    Ok(Some(match byte {
+        // Arrow keys arrive as `ESC [ A` through `ESC [ D`
+        0x1b => match (read_byte(tty)?, read_byte(tty)?) {
+            (Some(b'['), Some(b'A')) => Key::Up,
+            (Some(b'['), Some(b'B')) => Key::Down,
+            (Some(b'['), Some(b'C')) => Key::Expand,
+            (Some(b'['), Some(b'D')) => Key::Collapse,
+            _ => Key::Other,
+        },
+        b'k' => Key::Up,
+        b'j' => Key::Down,
+        b'l' | b'\r' => Key::Expand,
+        b'h' => Key::Collapse,
+        b' ' => Key::Toggle,
+        // Raw mode turns Ctrl-C into a plain byte
+        b'q' | 0x03 => Key::Quit,
+        _ => Key::Other,
+    }))
+}
+
+/// One byte from the terminal, or `None` once the read times out
+fn read_byte(tty: &mut fs::File) -> io::Result<Option<u8>> {
+    let mut byte: [u8; 1] = [0];
+    match tty.read(&mut byte) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(byte[0])),
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// SIGINT, SIGTERM and SIGHUP end the browser like `q` does, through the
+/// guard, instead of killing the process with the terminal still raw.
+///
+/// The handlers are installed with `sigaction` and no flags, so they stay
+/// installed and interrupt reads the same way everywhere, unlike `signal`.
+/// This crate builds without `libc`, so the declarations below are written
+/// out only for the platforms whose `struct sigaction` and signal numbers
+/// were checked against their headers; elsewhere signals are left alone,
+/// and Ctrl-C still quits since raw mode turns it into a key.
+#[cfg(any(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")), target_os = "macos"))]
+mod signals {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // The same numbers in Linux's and macOS's <signal.h>
+    const SIGHUP: i32 = 1;
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+
+    static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+    /// `struct sigaction` from glibc and musl: a 1024-bit mask, then the
+    /// flags and the restorer
+    #[cfg(target_os = "linux")]
+    #[repr(C)]
+    struct SigAction {
+        handler: usize,
+        mask: [u64; 16],
+        flags: i32,
+        restorer: usize,
+    }
+
+    /// `struct sigaction` from macOS: a 32-bit mask, then the flags
+    #[cfg(target_os = "macos")]
+    #[repr(C)]
+    struct SigAction {
+        handler: usize,
+        mask: u32,
+        flags: i32,
+    }
+
+    impl SigAction {
+        /// Runs `handler` with an empty mask and no flags
+        fn new(handler: usize) -> Self {
+            // SAFETY: all-zero is a valid `struct sigaction`: no handler,
+            // an empty mask and no flags
+            let mut action: SigAction = unsafe { std::mem::zeroed() };
+            action.handler = handler;
+            action
+        }
+    }
+
+    unsafe extern "C" {
+        fn sigaction(signum: i32, action: *const SigAction, previous: *mut SigAction) -> i32;
+    }
+
+    extern "C" fn interrupt(_signum: i32) {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+
+    /// The actions replaced by `catch`, put back when dropped
+    pub struct Caught {
+        previous: Vec<(i32, SigAction)>,
+    }
+
+    pub fn catch() -> Caught {
+        let handler: extern "C" fn(i32) = interrupt;
+        let mut previous: Vec<(i32, SigAction)> = Vec::new();
+        for signum in [SIGHUP, SIGINT, SIGTERM] {
+            let action: SigAction = SigAction::new(handler as usize);
+            let mut old: SigAction = SigAction::new(0);
+            // SAFETY: both pointers are to live `struct sigaction` values,
+            // and the handler only stores to an atomic, which is
+            // async-signal-safe
+            if unsafe { sigaction(signum, &action, &mut old) } == 0 {
+                previous.push((signum, old));
+            }
+        }
+        Caught { previous }
+    }
+
+    pub fn interrupted() -> bool {
+        INTERRUPTED.load(Ordering::Relaxed)
+    }
+
+    impl Drop for Caught {
+        fn drop(&mut self) {
+            for (signum, action) in &self.previous {
+                // SAFETY: puts back the action `sigaction` returned
+                unsafe { sigaction(*signum, action, std::ptr::null_mut()) };
+            }
+        }
+    }
+}
+
+#[cfg(not(any(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")), target_os = "macos")))]
+mod signals {
+    pub struct Caught;
+
+    pub fn catch() -> Caught {
+        Caught
+    }
+
+    pub fn interrupted() -> bool {
+        false
+    }
+}
+
+/// Rows of the terminal from `stty size`, or `LINES`, or 24
+fn terminal_height() -> usize {
+    terminal::size()
+        .map(|(rows, _)| rows)
+        .or_else(|| std::env::var("LINES").ok()?.parse().ok())
+        .filter(|&rows: &usize| rows > 0)
+        .unwrap_or(24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// What the fake terminal was asked to do, in order
+        static LOG: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn log(entry: String) {
+        LOG.with(|log: &RefCell<Vec<String>>| log.borrow_mut().push(entry));
+    }
+
+    fn take_log() -> Vec<String> {
+        LOG.with(|log: &RefCell<Vec<String>>| log.take())
+    }
+
+    fn fake_stty(args: &[&str]) -> io::Result<String> {
+        log(format!("stty {}", args.join(" ")));
+        Ok(if args == ["-g"] { String::from("saved-modes\n") } else { String::new() })
+    }
+
+    fn failing_stty(args: &[&str]) -> io::Result<String> {
+        log(format!("stty {}", args.join(" ")));
+        if args[0] == "raw" { Err(io::Error::other("no terminal")) } else { Ok(String::from("saved-modes\n")) }
+    }
+
+    struct FakeTty;
+
+    impl Write for FakeTty {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let text: String = String::from_utf8_lossy(buf).to_string();
+            log(match text.as_str() {
+                ENTER_SCREEN => String::from("enter screen"),
+                LEAVE_SCREEN => String::from("leave screen"),
+                _ => format!("write {:?}", text),
+            });
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn guard_restores_in_reverse_order() {
+        take_log();
+        let guard: TerminalGuard<FakeTty> = TerminalGuard::enter(FakeTty, fake_stty).unwrap();
+        assert_eq!(take_log(), ["stty -g", "stty raw -echo min 0 time 1", "enter screen"]);
+        drop(guard);
+        assert_eq!(take_log(), ["leave screen", "stty saved-modes"]);
+    }
+
+    #[test]
+    fn guard_restores_when_browsing_panics() {
+        take_log();
+        let result = std::panic::catch_unwind(|| {
+            let _guard: TerminalGuard<FakeTty> = TerminalGuard::enter(FakeTty, fake_stty).unwrap();
+            panic!("browsing failed");
+        });
+        assert!(result.is_err());
+        assert_eq!(take_log()[3..], ["leave screen", "stty saved-modes"]);
+    }
+
+    #[test]
+    fn nothing_is_restored_when_entering_fails() {
+        take_log();
+        assert!(TerminalGuard::enter(FakeTty, failing_stty).is_err());
+        assert_eq!(take_log(), ["stty -g", "stty raw -echo min 0 time 1"]);
+    }
+}
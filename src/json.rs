@@ -0,0 +1,122 @@
+/// Minimal JSON document model used by the JSON renderers
+#[derive(Debug)]
+pub enum JsonValue {
+    Number(u64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn object(fields: Vec<(&str, JsonValue)>) -> Self {
+        JsonValue::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn string(value: impl Into<String>) -> Self {
+        JsonValue::String(value.into())
+    }
+
+    /// Serializes the value, either indented by two spaces or on a single line
+    pub fn to_json(&self, pretty: bool) -> String {
+        let mut output: String = String::new();
+        // Pending pieces of output, so deeply nested documents need no
+        // recursion
+        let mut stack: Vec<Step> = vec![Step::Value(self, 0)];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Value(JsonValue::Number(value), _) => output.push_str(&value.to_string()),
+                Step::Value(JsonValue::String(value), _) => write_string(&mut output, value),
+                Step::Value(JsonValue::Array(items), _) if items.is_empty() => output.push_str("[]"),
+                Step::Value(JsonValue::Object(fields), _) if fields.is_empty() => output.push_str("{}"),
+                Step::Value(JsonValue::Array(items), indent) => {
+                    output.push('[');
+                    stack.push(Step::Close(']', indent));
+                    for (index, item) in items.iter().enumerate().rev() {
+                        stack.push(Step::Value(item, indent + 1));
+                        stack.push(Step::Item(index > 0, indent + 1));
+                    }
+                }
+                Step::Value(JsonValue::Object(fields), indent) => {
+                    output.push('{');
+                    stack.push(Step::Close('}', indent));
+                    for (index, (key, value)) in fields.iter().enumerate().rev() {
+                        stack.push(Step::Value(value, indent + 1));
+                        stack.push(Step::Key(key));
+                        stack.push(Step::Item(index > 0, indent + 1));
+                    }
+                }
+                Step::Item(separated, indent) => {
+                    if separated {
+                        output.push(',');
+                    }
+                    write_newline(&mut output, pretty, indent);
+                }
+                Step::Key(key) => {
+                    write_string(&mut output, key);
+                    output.push_str(if pretty { ": " } else { ":" });
+                }
+                Step::Close(bracket, indent) => {
+                    write_newline(&mut output, pretty, indent);
+                    output.push(bracket);
+                }
+            }
+        }
+        output
+    }
+}
+
+impl Drop for JsonValue {
+    fn drop(&mut self) {
+        // Nested values are moved onto a stack and dropped once emptied
+        let mut stack: Vec<JsonValue> = take_nested(self);
+        while let Some(mut value) = stack.pop() {
+            stack.append(&mut take_nested(&mut value));
+        }
+    }
+}
+
+/// Moves the items or field values out of an array or object
+fn take_nested(value: &mut JsonValue) -> Vec<JsonValue> {
+    match value {
+        JsonValue::Array(items) => std::mem::take(items),
+        JsonValue::Object(fields) => std::mem::take(fields).into_iter().map(|(_, value)| value).collect(),
+        JsonValue::Number(_) | JsonValue::String(_) => Vec::new(),
+    }
+}
+
+/// A piece of output still to be written by `JsonValue::to_json`
+enum Step<'a> {
+    Value(&'a JsonValue, usize),
+    /// The line break before an item, after a comma unless it is the first
+    Item(bool, usize),
+    Key(&'a str),
+    Close(char, usize),
+}
+
+fn write_newline(output: &mut String, pretty: bool, indent: usize) {
+    if pretty {
+        output.push('\n');
+        output.push_str(&"  ".repeat(indent));
+    }
+}
+
+fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
@@ -0,0 +1,25 @@
+pub mod checksum;
+pub mod collate;
+pub mod color;
+pub mod config;
+pub mod diff;
+pub mod encoding;
+pub mod error;
+pub mod exec;
+pub mod filetype;
+pub mod filter;
+pub mod git;
+pub mod gitignore;
+pub mod info;
+pub mod interactive;
+pub mod json;
+pub mod node;
+pub mod pattern;
+pub mod regex;
+pub mod size;
+pub mod stats;
+pub mod terminal;
+#[cfg(test)]
+mod testutil;
+pub mod time;
+pub mod tree;
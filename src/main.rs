@@ -1,13 +1,7 @@
-mod config;
-mod error;
-mod gitignore;
-mod stats;
-mod tree;
-
-use config::Config;
-use error::TreeResult;
 use std::process;
-use tree::TreeGenerator;
+use tree::config::Config;
+use tree::error::TreeResult;
+use tree::tree::TreeGenerator;
 
 fn main() {
     let config = Config::parse_args();
@@ -0,0 +1,177 @@
+use crate::diff::DiffMark;
+use crate::filetype::SpecialKind;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+/// An entry of the directory tree, built once and then rendered. Cloning and
+/// dropping work through an explicit stack, so a tree thousands of levels
+/// deep cannot overflow the call stack.
+#[derive(Debug)]
+pub struct TreeNode {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    /// FIFO, socket or device kind, `None` for regular files and directories
+    pub special: Option<SpecialKind>,
+    /// Size shown by -s; with --du a directory holds the total of everything
+    /// listed beneath it. `None` when sizes are not collected.
+    pub size: Option<u64>,
+    /// How the entry relates to the --diff tree; `None` when it is in both
+    /// trees unchanged, or without --diff
+    pub diff: Option<DiffMark>,
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    /// A plain entry with no size, diff mark or children yet
+    pub fn new(name: String, path: PathBuf, is_dir: bool) -> Self {
+        Self {
+            name,
+            path,
+            is_dir,
+            is_symlink: false,
+            special: None,
+            size: None,
+            diff: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Merges chains of directories that contain nothing but a single
+    /// subdirectory into one node named like `a/b/c`. Only directories
+    /// shallower than `depth_limit` (counting the children of this node as
+    /// depth 1) are merged; a chain stops at the first directory holding files
+    /// or more than one entry.
+    pub fn collapse_chains(&mut self, depth_limit: Option<usize>) {
+        let mut stack: Vec<(&mut TreeNode, usize)> =
+            self.children.iter_mut().map(|child: &mut TreeNode| (child, 1)).collect();
+        while let Some((node, mut depth)) = stack.pop() {
+            while node.is_dir
+                && node.children.len() == 1
+                && node.children[0].is_dir
+                && depth_limit.is_none_or(|limit: usize| depth < limit)
+            {
+                let mut child: TreeNode = node.children.remove(0);
+                node.name = format!("{}/{}", node.name, child.name);
+                node.path = std::mem::take(&mut child.path);
+                node.diff = child.diff;
+                node.children = std::mem::take(&mut child.children);
+                depth += 1;
+            }
+
+            stack.extend(node.children.iter_mut().map(|child: &mut TreeNode| (child, depth + 1)));
+        }
+    }
+
+    /// Keeps only the first `limit` descendants in breadth-first order, so
+    /// every level is shown before anything deeper. Returns the number of
+    /// direct children dropped from each directory; a dropped directory
+    /// counts as one entry, whatever it contains.
+    pub fn truncate_breadth_first(&mut self, limit: usize) -> Vec<(PathBuf, usize)> {
+        // Breadth-first order visits the children of a directory one after
+        // another, so the kept children of each directory are a prefix
+        let mut kept_counts: HashMap<PathBuf, usize> = HashMap::new();
+        let mut queue: VecDeque<&TreeNode> = VecDeque::from([&*self]);
+        let mut remaining: usize = limit;
+        while let Some(node) = queue.pop_front() {
+            let kept: usize = node.children.len().min(remaining);
+            remaining -= kept;
+            kept_counts.insert(node.path.clone(), kept);
+            queue.extend(&node.children[..kept]);
+        }
+
+        let mut omitted: Vec<(PathBuf, usize)> = Vec::new();
+        let mut stack: Vec<&mut TreeNode> = vec![self];
+        while let Some(node) = stack.pop() {
+            let kept: usize = kept_counts.get(&node.path).copied().unwrap_or(0);
+            if kept < node.children.len() {
+                omitted.push((node.path.clone(), node.children.len() - kept));
+                node.children.truncate(kept);
+            }
+            stack.extend(node.children.iter_mut());
+        }
+        omitted
+    }
+
+    /// Whether the entry only exists in the --diff tree, so it is shown but
+    /// not counted, searched or run on
+    pub fn is_removed(&self) -> bool {
+        self.diff == Some(DiffMark::Removed)
+    }
+
+    /// Iterates over this node and all of its descendants
+    pub fn iter(&self) -> TreeNodeIter<'_> {
+        TreeNodeIter {
+            stack: vec![(0, self)],
+        }
+    }
+}
+
+impl Clone for TreeNode {
+    fn clone(&self) -> Self {
+        // Copies are built bottom-up: a frame holds the children copied so far
+        let mut stack: Vec<(&TreeNode, Vec<TreeNode>)> = vec![(self, Vec::with_capacity(self.children.len()))];
+        loop {
+            let (node, copied) = stack.last_mut().expect("the root frame is popped last");
+            let node: &TreeNode = node;
+            if let Some(child) = node.children.get(copied.len()) {
+                stack.push((child, Vec::with_capacity(child.children.len())));
+                continue;
+            }
+
+            let (node, children) = stack.pop().expect("the root frame is popped last");
+            let copy: TreeNode = TreeNode {
+                name: node.name.clone(),
+                path: node.path.clone(),
+                is_dir: node.is_dir,
+                is_symlink: node.is_symlink,
+                special: node.special,
+                size: node.size,
+                diff: node.diff,
+                children,
+            };
+            match stack.last_mut() {
+                Some((_, siblings)) => siblings.push(copy),
+                None => return copy,
+            }
+        }
+    }
+}
+
+impl Drop for TreeNode {
+    fn drop(&mut self) {
+        // Every node is dropped with its children already moved out
+        let mut stack: Vec<TreeNode> = std::mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+/// Depth-first, pre-order traversal yielding `(depth, node)` pairs in display
+/// order: a directory comes before its children, which follow in sorted order.
+/// The node the iterator was created from is yielded first at depth 0.
+pub struct TreeNodeIter<'a> {
+    stack: Vec<(usize, &'a TreeNode)>,
+}
+
+impl<'a> Iterator for TreeNodeIter<'a> {
+    type Item = (usize, &'a TreeNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, node) = self.stack.pop()?;
+        self.stack
+            .extend(node.children.iter().rev().map(|child: &TreeNode| (depth + 1, child)));
+        Some((depth, node))
+    }
+}
+
+impl<'a> IntoIterator for &'a TreeNode {
+    type Item = (usize, &'a TreeNode);
+    type IntoIter = TreeNodeIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,277 @@
+use crate::config::PatternSyntax;
+use crate::regex::Regex;
+
+/// Matches text against a glob supporting `*` and `?`. Wildcards never match
+/// across a `/`, so anchored path patterns only match one component per `*`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let tokens: Vec<Token> = pattern
+        .chars()
+        .map(|c: char| match c {
+            '*' => Token::Star,
+            '?' => Token::One,
+            c => Token::Char(c),
+        })
+        .collect();
+    matches_tokens(&tokens, text)
+}
+
+/// Matches text against a pattern with full gitignore glob syntax, as used
+/// by `--strict-gitignore`: `*` and `?` stay within one path component, `**`
+/// spans components when it stands between slashes or at either end,
+/// `[a-z]` and `[!a-z]` match one character and `\` escapes the next one.
+pub fn wildmatch(pattern: &str, text: &str) -> bool {
+    let pattern_chars: Vec<char> = pattern.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut index: usize = 0;
+    while index < pattern_chars.len() {
+        let p: &[char] = &pattern_chars[index..];
+        let (token, length): (Option<Token>, usize) = match p[0] {
+            '*' if p.get(1) == Some(&'*') => match p.get(2) {
+                // A trailing `**` matches everything that is left
+                None => (Some(Token::AnyPath), 2),
+                // `**/` matches zero or more whole components
+                Some('/') => (Some(Token::Components), 3),
+                // Elsewhere `**` is an ordinary `*`
+                Some(_) => (None, 1),
+            },
+            '*' => (Some(Token::Star), 1),
+            '?' => (Some(Token::One), 1),
+            '[' => match parse_class(&p[1..]) {
+                Some((class, length)) => (Some(class), 1 + length),
+                // An unterminated class is a literal `[`
+                None => (Some(Token::Char('[')), 1),
+            },
+            '\\' if p.len() > 1 => (Some(Token::Char(p[1])), 2),
+            c => (Some(Token::Char(c)), 1),
+        };
+        tokens.extend(token);
+        index += length;
+    }
+
+    /// Parses the class following a `[`, returning it and the number of
+    /// pattern characters it used including the closing `]`
+    fn parse_class(p: &[char]) -> Option<(Token, usize)> {
+        let mut index: usize = 0;
+        let negated: bool = matches!(p.first(), Some('!' | '^'));
+        if negated {
+            index += 1;
+        }
+
+        let mut ranges: Vec<(char, char)> = Vec::new();
+        let mut first: bool = true;
+        loop {
+            let mut c: char = *p.get(index)?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            if c == '\\' {
+                index += 1;
+                c = *p.get(index)?;
+            }
+            index += 1;
+            if p.get(index) == Some(&'-') && p.get(index + 1).is_some_and(|&end| end != ']') {
+                ranges.push((c, p[index + 1]));
+                index += 2;
+            } else {
+                ranges.push((c, c));
+            }
+        }
+
+        Some((Token::Class { ranges, negated }, index + 1))
+    }
+
+    matches_tokens(&tokens, text)
+}
+
+/// One element of a compiled glob
+enum Token {
+    Char(char),
+    /// `?`: any character but `/`
+    One,
+    /// `[...]`, never matching `/`
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    /// `*`: any run of characters within one component
+    Star,
+    /// A trailing `**`: anything, separators included
+    AnyPath,
+    /// `**/`: nothing, or any run of text ending in `/`
+    Components,
+}
+
+/// Matches text against compiled glob tokens in time proportional to the
+/// product of their lengths, so no pattern can make matching blow up.
+/// `matched[j]` holds whether the tokens from the current one onwards match
+/// the text from `j`; rows are filled from the last token backwards.
+fn matches_tokens(tokens: &[Token], text: &str) -> bool {
+    let t: Vec<char> = text.chars().collect();
+    let mut next: Vec<bool> = (0..=t.len()).map(|j: usize| j == t.len()).collect();
+    let mut matched: Vec<bool> = vec![false; t.len() + 1];
+
+    for token in tokens.iter().rev() {
+        // Whether some `/` at or after `j` ends a `**/` match, for Components
+        let mut slash_ahead: bool = false;
+        for j in (0..=t.len()).rev() {
+            let c: Option<char> = t.get(j).copied();
+            matched[j] = match token {
+                Token::Char(expected) => c == Some(*expected) && next[j + 1],
+                Token::One => c.is_some_and(|c: char| c != '/') && next[j + 1],
+                Token::Class { ranges, negated } => {
+                    c.is_some_and(|c: char| {
+                        c != '/' && ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated
+                    }) && next[j + 1]
+                }
+                Token::Star => next[j] || (c.is_some_and(|c: char| c != '/') && matched[j + 1]),
+                Token::AnyPath => next[j] || (c.is_some() && matched[j + 1]),
+                Token::Components => {
+                    slash_ahead = slash_ahead || (c == Some('/') && next[j + 1]);
+                    next[j] || slash_ahead
+                }
+            };
+        }
+        std::mem::swap(&mut next, &mut matched);
+    }
+    next[0]
+}
+
+/// Matches a name against any of the given globs. Each glob may itself hold
+/// several alternatives separated by `|`, as with GNU tree's `-I`.
+pub fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns
+        .iter()
+        .flat_map(|pattern: &String| pattern.split('|'))
+        .any(|pattern: &str| glob_match(pattern, name))
+}
+
+/// The patterns of one option, compiled for the selected `--pattern-syntax`
+pub enum Patterns {
+    /// Globs, lowercased together with the names when matching ignores case
+    Glob { globs: Vec<String>, ignore_case: bool },
+    Regex(Vec<Regex>),
+}
+
+impl Patterns {
+    /// Compiles the patterns, failing with the first invalid regex
+    pub fn new(patterns: &[String], syntax: PatternSyntax, ignore_case: bool) -> Result<Self, String> {
+        match syntax {
+            PatternSyntax::Glob => Ok(Patterns::Glob {
+                globs: patterns
+                    .iter()
+                    .map(|pattern: &String| if ignore_case { pattern.to_lowercase() } else { pattern.clone() })
+                    .collect(),
+                ignore_case,
+            }),
+            PatternSyntax::Regex => patterns
+                .iter()
+                .map(|pattern: &String| {
+                    let regex: Result<Regex, String> =
+                        if ignore_case { Regex::new_ignoring_case(pattern) } else { Regex::new(pattern) };
+                    regex.map_err(|err: String| format!("invalid regex `{}`: {}", pattern, err))
+                })
+                .collect::<Result<Vec<Regex>, String>>()
+                .map(Patterns::Regex),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Patterns::Glob { globs, .. } => globs.is_empty(),
+            Patterns::Regex(regexes) => regexes.is_empty(),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Patterns::Glob { globs, ignore_case: true } => matches_any(globs, &name.to_lowercase()),
+            Patterns::Glob { globs, ignore_case: false } => matches_any(globs, name),
+            Patterns::Regex(regexes) => regexes.iter().any(|regex: &Regex| regex.is_match(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildmatch_leading_double_star_spans_components() {
+        assert!(wildmatch("**/foo", "foo"));
+        assert!(wildmatch("**/foo", "a/foo"));
+        assert!(wildmatch("**/foo", "a/b/foo"));
+        assert!(!wildmatch("**/foo", "a/barfoo"));
+        assert!(wildmatch("a/**/b", "a/b"));
+        assert!(wildmatch("a/**/b", "a/x/y/b"));
+        assert!(!wildmatch("a/**/b", "ab"));
+    }
+
+    #[test]
+    fn wildmatch_trailing_double_star_matches_everything_inside() {
+        assert!(wildmatch("abc/**", "abc/x"));
+        assert!(wildmatch("abc/**", "abc/x/y"));
+        assert!(!wildmatch("abc/**", "abc"));
+        assert!(!wildmatch("abc/**", "abcd/x"));
+    }
+
+    #[test]
+    fn wildmatch_single_star_stays_in_one_component() {
+        assert!(wildmatch("*.rs", "main.rs"));
+        assert!(!wildmatch("*.rs", "src/main.rs"));
+        assert!(wildmatch("src/*.rs", "src/main.rs"));
+        assert!(!wildmatch("src/*.rs", "src/bin/main.rs"));
+        // `**` not between slashes is an ordinary `*`
+        assert!(!wildmatch("a**b", "a/b"));
+        assert!(wildmatch("a**b", "axxb"));
+    }
+
+    #[test]
+    fn wildmatch_classes() {
+        assert!(wildmatch("file[0-9].txt", "file3.txt"));
+        assert!(!wildmatch("file[0-9].txt", "filex.txt"));
+        assert!(wildmatch("file[!0-9].txt", "filex.txt"));
+        assert!(!wildmatch("file[!0-9].txt", "file3.txt"));
+        assert!(wildmatch("file[^0-9].txt", "filex.txt"));
+        assert!(wildmatch("[]]", "]"));
+        assert!(wildmatch("[a-]", "-"));
+        assert!(!wildmatch("a[/]b", "a/b"));
+        // An unterminated class is a literal `[`
+        assert!(wildmatch("a[b", "a[b"));
+    }
+
+    #[test]
+    fn wildmatch_escapes() {
+        assert!(wildmatch(r"\*", "*"));
+        assert!(!wildmatch(r"\*", "a"));
+        assert!(wildmatch(r"\[a]", "[a]"));
+        assert!(wildmatch(r"foo\ ", "foo "));
+    }
+
+    #[test]
+    fn glob_match_stops_at_separators() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("src/?ain.rs", "src/main.rs"));
+        assert!(!glob_match("src?main.rs", "src/main.rs"));
+    }
+
+    /// Backtracking takes exponential time on this, which a `.gitignore`
+    /// from anyone's repository could exploit
+    #[test]
+    fn wildmatch_is_fast_on_pathological_patterns() {
+        let started: std::time::Instant = std::time::Instant::now();
+        let name: String = "a".repeat(40);
+        assert!(!wildmatch("*a*a*a*a*a*a*a*a*a*a*b", &name));
+        assert!(wildmatch("*a*a*a*a*a*a*a*a*a*a*", &name));
+        assert!(!wildmatch("**/a*a*a*a*a*a*a*a*a*a*b", &format!("{0}/{0}/{0}", name)));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn glob_match_is_fast_on_pathological_patterns() {
+        let started: std::time::Instant = std::time::Instant::now();
+        let name: String = "a".repeat(40);
+        assert!(!glob_match("*a*a*a*a*a*a*a*a*a*a*b", &name));
+        assert!(glob_match("*a*a*a*a*a*a*a*a*a*a*", &name));
+        assert!(!matches_any(&[String::from("x|*a*a*a*a*a*a*a*a*a*a*b")], &name));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,574 @@
+/// A regular expression for `--pattern-syntax regex`. Supports literals, `.`,
+/// classes like `[a-z]` and `[^.]`, the escapes `\d \w \s` (and their negated
+/// capitals), anchors `^ $`, groups, `|` and the quantifiers `* + ? {m,n}`.
+/// Matches anywhere in the text unless anchored.
+///
+/// Lookaround, named groups, inline flags, backreferences, `\b`-style
+/// anchors, Unicode and POSIX classes are rejected with an error naming the
+/// construct, never read as literals.
+///
+/// Patterns compile to a small NFA program that is run over all threads at
+/// once, so matching takes time linear in the text for any pattern and
+/// patterns like `(a|a)*b` cannot backtrack exponentially.
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+/// Instructions of a compiled pattern beyond this are rejected, which bounds
+/// both memory and the work per character of text
+const MAX_PROGRAM_LEN: usize = 10_000;
+
+#[derive(Debug)]
+enum Node {
+    Literal(char),
+    Any,
+    Class(CharClass),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(low, high)| low <= c && c <= high) != self.negated
+    }
+}
+
+/// One step of a compiled pattern
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(CharClass),
+    Start,
+    End,
+    /// Continue at both targets
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        Self::build(pattern, false)
+    }
+
+    /// Like `new`, but letters match in either case. Ranges in classes are
+    /// widened for ASCII letters only, so `[a-z]` also matches `Q` but
+    /// `[à-ÿ]` stays as is.
+    pub fn new_ignoring_case(pattern: &str) -> Result<Self, String> {
+        Self::build(pattern, true)
+    }
+
+    fn build(pattern: &str, ignore_case: bool) -> Result<Self, String> {
+        let mut parser: Parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let mut node: Node = parser.parse_alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(String::from("unmatched `)`"));
+        }
+        if ignore_case {
+            fold_case(&mut node);
+        }
+
+        let mut program: Vec<Inst> = Vec::new();
+        compile(&node, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Self { program })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        // `marks[pc]` is one past the text position `pc` was last added at
+        let mut marks: Vec<usize> = vec![0; self.program.len()];
+        let mut threads: Vec<usize> = Vec::new();
+
+        for pos in 0..=chars.len() {
+            // A match may start at any position
+            if self.add_thread(&mut threads, &mut marks, 0, pos, chars.len()) {
+                return true;
+            }
+            let Some(&c) = chars.get(pos) else {
+                break;
+            };
+
+            let mut next: Vec<usize> = Vec::new();
+            for &pc in &threads {
+                let consumed: bool = match &self.program[pc] {
+                    Inst::Char(expected) => *expected == c,
+                    Inst::Any => true,
+                    Inst::Class(class) => class.matches(c),
+                    _ => false,
+                };
+                if consumed && self.add_thread(&mut next, &mut marks, pc + 1, pos + 1, chars.len()) {
+                    return true;
+                }
+            }
+            threads = next;
+        }
+        false
+    }
+
+    /// Follows jumps, splits and anchors from `pc` at `pos`, collecting the
+    /// instructions waiting for a character; returns whether `Match` is reached
+    fn add_thread(&self, threads: &mut Vec<usize>, marks: &mut [usize], pc: usize, pos: usize, len: usize) -> bool {
+        let mut pending: Vec<usize> = vec![pc];
+        while let Some(pc) = pending.pop() {
+            if marks[pc] == pos + 1 {
+                continue;
+            }
+            marks[pc] = pos + 1;
+            match &self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(target) => pending.push(*target),
+                Inst::Split(first, second) => {
+                    pending.push(*second);
+                    pending.push(*first);
+                }
+                Inst::Start if pos == 0 => pending.push(pc + 1),
+                Inst::End if pos == len => pending.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                Inst::Char(_) | Inst::Any | Inst::Class(_) => threads.push(pc),
+            }
+        }
+        false
+    }
+}
+
+/// Appends the instructions for `node`; repetitions are unrolled, so
+/// `x{2,4}` becomes `x x x? x?`
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    if program.len() > MAX_PROGRAM_LEN {
+        return Err(String::from("pattern is too large"));
+    }
+
+    match node {
+        Node::Literal(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program)?;
+            }
+        }
+        Node::Alternate(branches) => {
+            let mut jumps: Vec<usize> = Vec::new();
+            for (index, branch) in branches.iter().enumerate() {
+                if index + 1 == branches.len() {
+                    compile(branch, program)?;
+                    break;
+                }
+                let split: usize = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(branch, program)?;
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            for jump in jumps {
+                program[jump] = Inst::Jump(program.len());
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                None => {
+                    let split: usize = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    for _ in *min..*max {
+                        let split: usize = program.len();
+                        program.push(Inst::Split(split + 1, 0));
+                        compile(node, program)?;
+                        program[split] = Inst::Split(split + 1, program.len());
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c: Option<char> = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, String> {
+        let mut branches: Vec<Node> = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.parse_concat()?);
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.remove(0))
+        } else {
+            Ok(Node::Alternate(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut items: Vec<Node> = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            let mut atom: Node = self.parse_atom()?;
+            while let Some((min, max)) = self.parse_quantifier()? {
+                atom = Node::Repeat {
+                    node: Box::new(atom),
+                    min,
+                    max,
+                };
+            }
+            items.push(atom);
+        }
+
+        Ok(Node::Concat(items))
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('(') => {
+                // Groups never capture, so `(?:...)` is accepted as a plain group
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                } else if self.peek() == Some('?') {
+                    return Err(String::from(
+                        "`(?` groups other than `(?:` are not supported (lookaround, named groups, inline flags)",
+                    ));
+                }
+                let node: Node = self.parse_alternation()?;
+                if self.next() != Some(')') {
+                    return Err(String::from("unclosed `(`"));
+                }
+                Ok(node)
+            }
+            Some('[') => self.parse_class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('\\') => self.parse_escape(),
+            Some(c @ ('*' | '+' | '?')) => Err(format!("nothing to repeat before `{}`", c)),
+            Some(c) => Ok(Node::Literal(c)),
+            None => Err(String::from("unexpected end of pattern")),
+        }
+    }
+
+    /// Parses `*`, `+`, `?` or `{m}`, `{m,}`, `{m,n}`. A `{` that does not
+    /// start a valid count is left to be read as a literal.
+    fn parse_quantifier(&mut self) -> Result<Option<(usize, Option<usize>)>, String> {
+        let quantifier: (usize, Option<usize>) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let Some(close) = self.chars[self.pos..].iter().position(|&c| c == '}') else {
+                    return Ok(None);
+                };
+                let body: String = self.chars[self.pos + 1..self.pos + close].iter().collect();
+                let (min, max) = match body.split_once(',') {
+                    None => (body.parse::<usize>().ok(), body.parse::<usize>().ok().map(Some)),
+                    Some((min, "")) => (min.parse::<usize>().ok(), Some(None)),
+                    Some((min, max)) => (min.parse::<usize>().ok(), max.parse::<usize>().ok().map(Some)),
+                };
+                let (Some(min), Some(max)) = (min, max) else {
+                    return Ok(None);
+                };
+                if max.is_some_and(|max: usize| max < min) {
+                    return Err(format!("invalid repetition {{{}}}", body));
+                }
+                self.pos += close;
+                (min, max)
+            }
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+
+        // Matching only answers yes or no, so lazy quantifiers act greedy
+        if self.peek() == Some('?') {
+            self.pos += 1;
+        }
+        Ok(Some(quantifier))
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let c: char = self.next().ok_or("trailing `\\`")?;
+        match c {
+            '1'..='9' => return Err(format!("backreferences like `\\{}` are not supported", c)),
+            'b' | 'B' | 'A' | 'z' | 'Z' => return Err(format!("the anchor `\\{}` is not supported", c)),
+            'p' | 'P' => return Err(format!("Unicode classes like `\\{}{{..}}` are not supported", c)),
+            _ => {}
+        }
+        if let Some(class) = shorthand_class(c) {
+            return Ok(Node::Class(class));
+        }
+        escaped_char(c).map(Node::Literal)
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated: bool = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut ranges: Vec<(char, char)> = Vec::new();
+        let mut first: bool = true;
+        loop {
+            let c: char = self.next().ok_or("unclosed `[`")?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            if c == '[' && self.peek() == Some(':') {
+                return Err(String::from("POSIX classes like `[:alpha:]` are not supported"));
+            }
+
+            let low: char = if c == '\\' {
+                let escaped: char = self.next().ok_or("unclosed `[`")?;
+                if let Some(class) = shorthand_class(escaped) {
+                    if class.negated {
+                        return Err(format!("`\\{}` is not supported inside `[]`", escaped));
+                    }
+                    ranges.extend(class.ranges);
+                    continue;
+                }
+                escaped_char(escaped)?
+            } else {
+                c
+            };
+
+            // A `-` right before `]` is a literal dash
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&next| next != ']') {
+                self.pos += 1;
+                let high: char = match self.next().ok_or("unclosed `[`")? {
+                    '\\' => escaped_char(self.next().ok_or("unclosed `[`")?)?,
+                    high => high,
+                };
+                if high < low {
+                    return Err(format!("invalid range `{}-{}`", low, high));
+                }
+                ranges.push((low, high));
+            } else {
+                ranges.push((low, low));
+            }
+        }
+
+        Ok(Node::Class(CharClass { ranges, negated }))
+    }
+}
+
+fn shorthand_class(c: char) -> Option<CharClass> {
+    let ranges: Vec<(char, char)> = match c.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        's' => vec![(' ', ' '), ('\t', '\r')],
+        _ => return None,
+    };
+    Some(CharClass {
+        ranges,
+        negated: c.is_ascii_uppercase(),
+    })
+}
+
+fn escaped_char(c: char) -> Result<char, String> {
+    match c {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        c if c.is_ascii_alphanumeric() => Err(format!("unknown escape `\\{}`", c)),
+        c => Ok(c),
+    }
+}
+
+fn fold_case(node: &mut Node) {
+    match node {
+        Node::Literal(c) => {
+            let mut variants: Vec<char> = vec![*c];
+            // Only single-character mappings can stand in for one character
+            for folded in [c.to_lowercase().to_string(), c.to_uppercase().to_string()] {
+                let mut chars = folded.chars();
+                if let (Some(variant), None) = (chars.next(), chars.next())
+                    && !variants.contains(&variant)
+                {
+                    variants.push(variant);
+                }
+            }
+            if variants.len() > 1 {
+                *node = Node::Class(CharClass {
+                    ranges: variants.into_iter().map(|variant: char| (variant, variant)).collect(),
+                    negated: false,
+                });
+            }
+        }
+        Node::Class(class) => {
+            let mut folded: Vec<(char, char)> = Vec::new();
+            for &(low, high) in &class.ranges {
+                for (from, to) in [('a', 'z'), ('A', 'Z')] {
+                    let (start, end): (char, char) = (low.max(from), high.min(to));
+                    if start <= end {
+                        folded.push((swap_ascii_case(start), swap_ascii_case(end)));
+                    }
+                }
+            }
+            class.ranges.extend(folded);
+        }
+        Node::Concat(nodes) | Node::Alternate(nodes) => nodes.iter_mut().for_each(fold_case),
+        Node::Repeat { node, .. } => fold_case(node),
+        Node::Any | Node::Start | Node::End => {}
+    }
+}
+
+fn swap_ascii_case(c: char) -> char {
+    if c.is_ascii_lowercase() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regex;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).expect("pattern should compile").is_match(text)
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches("^a", "abc"));
+        assert!(!matches("^a", "ba"));
+        assert!(matches("b$", "ab"));
+        assert!(!matches("b$", "ba"));
+        assert!(matches("^$", ""));
+        assert!(!matches("^$", "a"));
+        assert!(matches("a", "bab"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("^[a-c]+$", "abcabc"));
+        assert!(!matches("^[a-c]+$", "abd"));
+        assert!(matches("^[^.]+$", "Makefile"));
+        assert!(!matches("^[^.]+$", "main.rs"));
+        assert!(matches(r"^\d\d$", "42"));
+        assert!(!matches(r"^\d\d$", "4a"));
+        assert!(matches(r"\W", "a-b"));
+        assert!(!matches(r"\W", "a_b"));
+        assert!(matches(r"^a\.b$", "a.b"));
+        assert!(!matches(r"^a\.b$", "axb"));
+        assert!(matches("^a.b$", "axb"));
+    }
+
+    #[test]
+    fn alternation() {
+        assert!(matches(r"\.(rs|toml)$", "Cargo.toml"));
+        assert!(matches(r"\.(rs|toml)$", "main.rs"));
+        assert!(!matches(r"\.(rs|toml)$", "main.c"));
+        assert!(matches("^(a|bc|)$", ""));
+        assert!(matches("^(a|bc|)$", "bc"));
+        assert!(!matches("^(a|bc|)$", "b"));
+    }
+
+    #[test]
+    fn repetition() {
+        assert!(matches("^ab*c$", "ac"));
+        assert!(matches("^ab*c$", "abbbc"));
+        assert!(!matches("^ab+c$", "ac"));
+        assert!(matches("^ab+c$", "abc"));
+        assert!(matches("^ab?c$", "ac"));
+        assert!(!matches("^ab?c$", "abbc"));
+        assert!(!matches("^a{2,3}$", "a"));
+        assert!(matches("^a{2,3}$", "aa"));
+        assert!(matches("^a{2,3}$", "aaa"));
+        assert!(!matches("^a{2,3}$", "aaaa"));
+        assert!(matches("^a{2}$", "aa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(matches("^(a*)*b$", "aaab"));
+        assert!(!matches("^(a*)*b$", "aaa"));
+    }
+
+    #[test]
+    fn nested_quantifiers_run_in_linear_time() {
+        let text: String = "a".repeat(10_000);
+        assert!(!matches("^(a|a)*b$", &text));
+        assert!(!matches("(a*)*b", &text));
+        assert!(!matches("^(a+)+$", &format!("{}!", text)));
+    }
+
+    #[test]
+    fn ignoring_case() {
+        let regex: Regex = Regex::new_ignoring_case("^readme\\.[a-z]+$").unwrap();
+        assert!(regex.is_match("README.MD"));
+        assert!(regex.is_match("ReadMe.txt"));
+        assert!(!regex.is_match("README"));
+        assert!(!Regex::new("^readme$").unwrap().is_match("README"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+        assert!(Regex::new("[a").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new(r"\q").is_err());
+        assert!(Regex::new("a{3,2}").is_err());
+        assert_eq!(Regex::new("(a{1000}){1000}").unwrap_err(), "pattern is too large");
+    }
+
+    /// Syntax other engines accept fails loudly instead of matching literally
+    #[test]
+    fn unsupported_syntax_is_rejected() {
+        let unsupported: [(&str, &str); 9] = [
+            ("a(?=b)", "`(?` groups other than `(?:` are not supported (lookaround, named groups, inline flags)"),
+            ("(?<name>a)", "`(?` groups other than `(?:` are not supported (lookaround, named groups, inline flags)"),
+            ("(?i)a", "`(?` groups other than `(?:` are not supported (lookaround, named groups, inline flags)"),
+            (r"(a)\1", "backreferences like `\\1` are not supported"),
+            (r"\bword", "the anchor `\\b` is not supported"),
+            (r"\Aa", "the anchor `\\A` is not supported"),
+            (r"\p{L}", "Unicode classes like `\\p{..}` are not supported"),
+            ("[[:alpha:]]", "POSIX classes like `[:alpha:]` are not supported"),
+            (r"\q", "unknown escape `\\q`"),
+        ];
+        for (pattern, message) in unsupported {
+            assert_eq!(Regex::new(pattern).unwrap_err(), message, "{}", pattern);
+        }
+        // A `[` inside a class is still an ordinary character
+        assert!(matches("[[a]", "["));
+    }
+}
@@ -0,0 +1,99 @@
+/// Units of `format_human_size`, each 1024 times the previous one
+const UNITS: [&str; 7] = ["B", "KB", "MB", "GB", "TB", "PB", "EB"];
+
+/// Units of `format_si_size`, each 1000 times the previous one
+const SI_UNITS: [&str; 7] = ["B", "kB", "MB", "GB", "TB", "PB", "EB"];
+
+/// Formats a byte count with one decimal in the largest fitting unit, e.g.
+/// `512 B` or `4.2 MB`
+pub fn format_human_size(bytes: u64) -> String {
+    format_scaled(bytes, 1024, &UNITS)
+}
+
+/// Like `format_human_size`, but in powers of 1000 as `du --si` and disk
+/// manufacturers count, e.g. `4.4 MB` for 4,404,019 bytes
+pub fn format_si_size(bytes: u64) -> String {
+    format_scaled(bytes, 1000, &SI_UNITS)
+}
+
+fn format_scaled(bytes: u64, base: u64, units: &[&str]) -> String {
+    if bytes < base {
+        return format!("{} {}", bytes, units[0]);
+    }
+
+    let mut value: f64 = bytes as f64;
+    let mut unit: usize = 0;
+    // Compare what is printed, so 1,048,575 bytes is `1.0 MB`, not `1024.0 KB`
+    while (value * 10.0).round() / 10.0 >= base as f64 && unit < units.len() - 1 {
+        value /= base as f64;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, units[unit])
+}
+
+/// Parses a size such as `512`, `10K`, `1.5M` or `2GB`. Suffixes are
+/// case-insensitive and 1024-based; a bare number means bytes.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid size `{}`, expected e.g. 512, 10K, 1.5M or 2G", text);
+
+    let split: usize = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let unit: String = unit.to_ascii_uppercase();
+    let exponent: i32 = match unit.strip_suffix('B').unwrap_or(&unit) {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        "E" => 6,
+        _ => return Err(invalid()),
+    };
+    let bytes: f64 = number * 1024f64.powi(exponent);
+    if bytes >= u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(bytes as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn human_sizes_step_up_at_1024() {
+        assert_eq!(format_human_size(0), "0 B");
+        assert_eq!(format_human_size(1000), "1000 B");
+        assert_eq!(format_human_size(1023), "1023 B");
+        assert_eq!(format_human_size(1024), "1.0 KB");
+        assert_eq!(format_human_size(1536), "1.5 KB");
+        assert_eq!(format_human_size(999_949), "976.5 KB");
+        assert_eq!(format_human_size(1_048_575), "1.0 MB");
+        assert_eq!(format_human_size(1_048_576), "1.0 MB");
+        assert_eq!(format_human_size(u64::MAX), "16.0 EB");
+    }
+
+    #[test]
+    fn si_sizes_step_up_at_1000() {
+        assert_eq!(format_si_size(999), "999 B");
+        assert_eq!(format_si_size(1000), "1.0 kB");
+        assert_eq!(format_si_size(1023), "1.0 kB");
+        assert_eq!(format_si_size(1024), "1.0 kB");
+        assert_eq!(format_si_size(999_949), "999.9 kB");
+        assert_eq!(format_si_size(999_950), "1.0 MB");
+        assert_eq!(format_si_size(4_404_019), "4.4 MB");
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("1.5m"), Ok(1024 * 1024 * 3 / 2));
+        assert_eq!(parse_size("2GB"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("").is_err());
+    }
+}
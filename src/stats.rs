@@ -1,11 +1,62 @@
+use std::collections::HashMap;
+
 #[derive(Debug, Default)]
 pub struct FileStats {
     pub dirs: usize,
     pub files: usize,
+    pub hidden: usize,
+    /// Entries skipped by `.gitignore` patterns with `-g`
+    pub ignored: usize,
+    pub links: usize,
+    pub bytes: u64,
+    /// Entries only in the primary tree with `--diff`
+    pub added: usize,
+    /// Entries only in the other tree with `--diff`
+    pub removed: usize,
+    /// Files in both trees that differ with `--diff-content`
+    pub modified: usize,
+    /// Basenames shared by several files with `--duplicate-names`
+    pub duplicate_groups: usize,
+    /// Directories and files at each depth with `--per-level-stats`, starting
+    /// with the root's entries
+    pub levels: Vec<(usize, usize)>,
+    /// Bytes of files per lowercase extension with `--size-by-ext`; files
+    /// without an extension are summed under the empty string
+    pub ext_bytes: HashMap<String, u64>,
 }
 
 impl FileStats {
     pub fn new() -> Self {
         Self::default()
     }
+
+    pub fn add(&mut self, other: &FileStats) {
+        self.dirs += other.dirs;
+        self.files += other.files;
+        self.hidden += other.hidden;
+        self.ignored += other.ignored;
+        self.links += other.links;
+        self.bytes += other.bytes;
+        self.added += other.added;
+        self.removed += other.removed;
+        self.modified += other.modified;
+        self.duplicate_groups += other.duplicate_groups;
+        for (index, &(dirs, files)) in other.levels.iter().enumerate() {
+            let level: &mut (usize, usize) = self.level_mut(index + 1);
+            level.0 += dirs;
+            level.1 += files;
+        }
+        for (ext, bytes) in &other.ext_bytes {
+            *self.ext_bytes.entry(ext.clone()).or_default() += bytes;
+        }
+    }
+
+    /// The counts at `depth`, where the root's entries are at depth 1
+    pub fn level_mut(&mut self, depth: usize) -> &mut (usize, usize) {
+        let index: usize = depth.saturating_sub(1);
+        if self.levels.len() <= index {
+            self.levels.resize(index + 1, (0, 0));
+        }
+        &mut self.levels[index]
+    }
 }
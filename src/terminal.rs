@@ -0,0 +1,24 @@
+use std::fs;
+use std::io;
+use std::process::{Command, Stdio};
+
+/// Runs `stty` on the controlling terminal and returns what it printed
+pub fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(fs::File::open("/dev/tty")?)
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty could not configure the terminal"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Rows and columns of the controlling terminal from `stty size`, or `None`
+/// without one. Either is 0 when the terminal does not know it.
+pub fn size() -> Option<(usize, usize)> {
+    let size: String = stty(&["size"]).ok()?;
+    let mut fields = size.split_whitespace().map(|field: &str| field.parse::<usize>().ok());
+    Some((fields.next()??, fields.next()??))
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A scratch directory under the system temp directory for tests that need
+/// real files, removed with everything in it when dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(label: &str) -> Self {
+        let id: usize = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path: PathBuf = std::env::temp_dir().join(format!("tree-test-{}-{}-{}", label, std::process::id(), id));
+        fs::create_dir_all(&path).expect("cannot create test directory");
+        // Canonical, so paths compare equal to those found by the traversal
+        let path: PathBuf = path.canonicalize().expect("cannot resolve test directory");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, relative: &str) -> PathBuf {
+        self.path.join(relative)
+    }
+
+    /// Creates a directory and its parents
+    pub fn dir(&self, relative: &str) -> PathBuf {
+        let path: PathBuf = self.join(relative);
+        fs::create_dir_all(&path).expect("cannot create test directory");
+        path
+    }
+
+    /// Creates an empty file and its parent directories
+    pub fn file(&self, relative: &str) -> PathBuf {
+        self.write(relative, "")
+    }
+
+    /// Creates a file with `contents` and its parent directories
+    pub fn write(&self, relative: &str, contents: &str) -> PathBuf {
+        let path: PathBuf = self.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("cannot create test directory");
+        }
+        fs::write(&path, contents).expect("cannot write test file");
+        path
+    }
+
+    /// Creates a symlink at `relative` pointing to `target` as given
+    #[cfg(unix)]
+    pub fn symlink(&self, target: &str, relative: &str) -> PathBuf {
+        let path: PathBuf = self.join(relative);
+        std::os::unix::fs::symlink(target, &path).expect("cannot create test symlink");
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
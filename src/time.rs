@@ -0,0 +1,420 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const WEEKDAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// A broken-down UTC timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub weekday: u32,
+}
+
+impl DateTime {
+    pub fn from_system_time(time: SystemTime) -> Self {
+        Self::from_unix(unix_seconds(time))
+    }
+
+    pub fn from_unix(secs: i64) -> Self {
+        let days: i64 = secs.div_euclid(86_400);
+        let seconds_of_day: i64 = secs.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (seconds_of_day / 3600) as u32,
+            minute: (seconds_of_day % 3600 / 60) as u32,
+            second: (seconds_of_day % 60) as u32,
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+}
+
+/// Converts days since the Unix epoch into a proleptic Gregorian date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z: i64 = days + 719_468;
+    let era: i64 = z.div_euclid(146_097);
+    let doe: i64 = z.rem_euclid(146_097);
+    let yoe: i64 = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: i64 = (5 * doy + 2) / 153;
+    let day: u32 = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month: u32 = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year: i64 = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a proleptic Gregorian date into days since the Unix epoch
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year: i64 = year - i64::from(month <= 2);
+    let era: i64 = year.div_euclid(400);
+    let yoe: i64 = year.rem_euclid(400);
+    let mp: i64 = (i64::from(month) + 9) % 12;
+    let doy: i64 = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe: i64 = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A UTC date given on the command line as `YYYY-MM-DD`, optionally followed
+/// by `THH:MM` or `THH:MM:SS` (a space works instead of `T`). A bound covers
+/// the whole day, minute or second it names, so both ends of a range are
+/// inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateBound {
+    start: i64,
+    span: i64,
+}
+
+impl DateBound {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid date `{}`, expected YYYY-MM-DD[THH:MM[:SS]]", text);
+
+        let (date, time): (&str, Option<&str>) = match text.split_once(['T', ' ']) {
+            Some((date, time)) => (date, Some(time)),
+            None => (text, None),
+        };
+
+        let date_parts: Vec<&str> = date.split('-').collect();
+        let [year, month, day] = date_parts.as_slice() else {
+            return Err(invalid());
+        };
+        let year: i64 = year.parse().map_err(|_| invalid())?;
+        let month: u32 = month.parse().map_err(|_| invalid())?;
+        let day: u32 = day.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(invalid());
+        }
+
+        let (seconds_of_day, span): (i64, i64) = match time {
+            None => (0, 86_400),
+            Some(time) => {
+                let time_parts: Vec<u32> = time
+                    .split(':')
+                    .map(|part: &str| part.parse::<u32>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| invalid())?;
+                let (hour, minute, second, span) = match time_parts.as_slice() {
+                    [hour, minute] => (*hour, *minute, 0, 60),
+                    [hour, minute, second] => (*hour, *minute, *second, 1),
+                    _ => return Err(invalid()),
+                };
+                if hour > 23 || minute > 59 || second > 59 {
+                    return Err(invalid());
+                }
+                (i64::from(hour * 3600 + minute * 60 + second), span)
+            }
+        };
+
+        Ok(Self {
+            start: days_from_civil(year, month, day) * 86_400 + seconds_of_day,
+            span,
+        })
+    }
+
+    /// Whether `time` falls within or after the period
+    pub fn is_on_or_after(&self, time: SystemTime) -> bool {
+        unix_seconds(time) >= self.start
+    }
+
+    /// Whether `time` falls within or before the period
+    pub fn is_on_or_before(&self, time: SystemTime) -> bool {
+        unix_seconds(time) < self.start + self.span
+    }
+}
+
+/// Parses a duration such as `90d`, `12h` or `2w`. The unit is one of
+/// `s`, `m`, `h`, `d`, `w` or `y` (365 days); a bare number means days.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration `{}`, expected e.g. 30s, 15m, 12h, 90d, 2w or 1y", text);
+
+    let split: usize = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (count, unit) = text.split_at(split);
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let seconds: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "" | "d" => 86_400,
+        "w" => 604_800,
+        "y" => 31_536_000,
+        _ => return Err(invalid()),
+    };
+    count.checked_mul(seconds).map(Duration::from_secs).ok_or_else(invalid)
+}
+
+/// When the system booted: read from `/proc/stat` on Linux and from the
+/// `kern.boottime` sysctl on other Unix systems. `None` when unknown.
+#[cfg(unix)]
+pub fn boot_time() -> Option<SystemTime> {
+    #[cfg(target_os = "linux")]
+    let secs: u64 = std::fs::read_to_string("/proc/stat")
+        .ok()?
+        .lines()
+        .find_map(|line: &str| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()?;
+
+    // Prints e.g. `{ sec = 1700000000, usec = 0 } Tue Nov 14 22:13:20 2023`
+    #[cfg(not(target_os = "linux"))]
+    let secs: u64 = {
+        let output: std::process::Output = std::process::Command::new("sysctl")
+            .args(["-n", "kern.boottime"])
+            .output()
+            .ok()?;
+        let text: String = String::from_utf8_lossy(&output.stdout).to_string();
+        let (_, rest) = text.split_once("sec = ")?;
+        rest.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()?
+    };
+
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[cfg(not(unix))]
+pub fn boot_time() -> Option<SystemTime> {
+    None
+}
+
+/// Formats a timestamp with a strftime-style format string. Supported
+/// specifiers: `%Y %y %m %d %e %H %M %S %b %a %s %%`. Times are in UTC.
+pub fn format_time(time: SystemTime, format: &str) -> String {
+    let date: DateTime = DateTime::from_system_time(time);
+    let mut output: String = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('Y') => output.push_str(&date.year.to_string()),
+            Some('y') => output.push_str(&format!("{:02}", date.year.rem_euclid(100))),
+            Some('m') => output.push_str(&format!("{:02}", date.month)),
+            Some('d') => output.push_str(&format!("{:02}", date.day)),
+            Some('e') => output.push_str(&format!("{:>2}", date.day)),
+            Some('H') => output.push_str(&format!("{:02}", date.hour)),
+            Some('M') => output.push_str(&format!("{:02}", date.minute)),
+            Some('S') => output.push_str(&format!("{:02}", date.second)),
+            Some('b') => output.push_str(MONTH_NAMES[date.month as usize - 1]),
+            Some('a') => output.push_str(WEEKDAY_NAMES[date.weekday as usize]),
+            Some('s') => output.push_str(&unix_seconds(time).to_string()),
+            Some('%') => output.push('%'),
+            Some(other) => {
+                output.push('%');
+                output.push(other);
+            }
+            None => output.push('%'),
+        }
+    }
+
+    output
+}
+
+/// Formats how long ago a timestamp was, e.g. `3 days ago` or `in 1 hour`
+pub fn format_relative(time: SystemTime, now: SystemTime) -> String {
+    let (elapsed, in_future): (Duration, bool) = match now.duration_since(time) {
+        Ok(elapsed) => (elapsed, false),
+        Err(err) => (err.duration(), true),
+    };
+
+    let secs: u64 = elapsed.as_secs();
+    if secs < 1 {
+        return String::from("just now");
+    }
+
+    let (count, unit): (u64, &str) = match secs {
+        0..60 => (secs, "second"),
+        60..3_600 => (secs / 60, "minute"),
+        3_600..86_400 => (secs / 3_600, "hour"),
+        86_400..2_592_000 => (secs / 86_400, "day"),
+        2_592_000..31_536_000 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    let plural: &str = if count == 1 { "" } else { "s" };
+
+    if in_future {
+        format!("in {} {}{}", count, unit, plural)
+    } else {
+        format!("{} {}{} ago", count, unit, plural)
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> SystemTime {
+        if secs >= 0 {
+            UNIX_EPOCH + Duration::from_secs(secs as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+        }
+    }
+
+    #[test]
+    fn civil_dates_from_unix_seconds() {
+        // (seconds, year, month, day, hour, minute, second, weekday)
+        for (secs, year, month, day, hour, minute, second, weekday) in [
+            (0, 1970, 1, 1, 0, 0, 0, 4),
+            (-1, 1969, 12, 31, 23, 59, 59, 3),
+            (86_399, 1970, 1, 1, 23, 59, 59, 4),
+            (86_400, 1970, 1, 2, 0, 0, 0, 5),
+            (951_782_400, 2000, 2, 29, 0, 0, 0, 2),
+            (1_709_251_199, 2024, 2, 29, 23, 59, 59, 4),
+            (4_107_542_400, 2100, 3, 1, 0, 0, 0, 1),
+            (-2_203_891_200, 1900, 3, 1, 0, 0, 0, 4),
+            (-11_676_096_000, 1600, 1, 1, 0, 0, 0, 6),
+        ] {
+            let expected: DateTime = DateTime { year, month, day, hour, minute, second, weekday };
+            assert_eq!(DateTime::from_unix(secs), expected, "{}", secs);
+            assert_eq!(DateTime::from_system_time(at(secs)), expected, "{}", secs);
+        }
+    }
+
+    #[test]
+    fn civil_conversions_round_trip() {
+        for days in (-800_000..800_000).step_by(997) {
+            let (year, month, day) = civil_from_days(days);
+            assert!((1..=days_in_month(year, month)).contains(&day), "{}", days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn leap_years_follow_the_gregorian_rules() {
+        for (year, february) in [(1900, 28), (2000, 29), (2023, 28), (2024, 29), (2100, 28), (2400, 29)] {
+            assert_eq!(days_in_month(year, 2), february, "{}", year);
+        }
+        assert!(DateBound::parse("2024-02-29").is_ok());
+        assert!(DateBound::parse("2023-02-29").is_err());
+        assert!(DateBound::parse("1900-02-29").is_err());
+        assert!(DateBound::parse("2000-02-29").is_ok());
+    }
+
+    #[test]
+    fn date_bounds_cover_the_period_they_name() {
+        // (bound, first second inside, span in seconds)
+        for (text, start, span) in [
+            ("1970-01-01", 0, 86_400),
+            ("1969-12-31", -86_400, 86_400),
+            ("2024-02-29", 1_709_164_800, 86_400),
+            ("2024-02-29T12:30", 1_709_209_800, 60),
+            ("2024-02-29 12:30", 1_709_209_800, 60),
+            ("2024-02-29T23:59:59", 1_709_251_199, 1),
+        ] {
+            let bound: DateBound = DateBound::parse(text).unwrap();
+            assert!(bound.is_on_or_after(at(start)) && bound.is_on_or_before(at(start)), "{}", text);
+            let end: i64 = start + span - 1;
+            assert!(bound.is_on_or_after(at(end)) && bound.is_on_or_before(at(end)), "{}", text);
+            assert!(!bound.is_on_or_after(at(start - 1)), "{}", text);
+            assert!(!bound.is_on_or_before(at(end + 1)), "{}", text);
+        }
+    }
+
+    #[test]
+    fn invalid_date_bounds_are_rejected() {
+        for text in [
+            "",
+            "2024",
+            "2024-03",
+            "2024-3-1-1",
+            "2024-00-10",
+            "2024-13-01",
+            "2024-04-31",
+            "2024-01-00",
+            "2024-01-01T24:00",
+            "2024-01-01T12:60",
+            "2024-01-01T12:00:60",
+            "2024-01-01T12",
+            "2024-01-01T12:00:00:00",
+            "yesterday",
+        ] {
+            assert_eq!(
+                DateBound::parse(text),
+                Err(format!("invalid date `{}`, expected YYYY-MM-DD[THH:MM[:SS]]", text))
+            );
+        }
+    }
+
+    #[test]
+    fn durations_parse_with_units() {
+        for (text, secs) in [
+            ("0", 0),
+            ("30s", 30),
+            ("15m", 900),
+            ("12h", 43_200),
+            ("90", 7_776_000),
+            ("90d", 7_776_000),
+            ("2w", 1_209_600),
+            ("1y", 31_536_000),
+        ] {
+            assert_eq!(parse_duration(text), Ok(Duration::from_secs(secs)), "{}", text);
+        }
+        for text in ["", "d", "1.5h", "-1d", "10x", "12H", "99999999999999999999y"] {
+            assert_eq!(
+                parse_duration(text),
+                Err(format!("invalid duration `{}`, expected e.g. 30s, 15m, 12h, 90d, 2w or 1y", text))
+            );
+        }
+    }
+
+    #[test]
+    fn relative_times_count_whole_units() {
+        let now: SystemTime = at(1_700_000_000);
+        for (offset, past, future) in [
+            (0, "just now", "just now"),
+            (1, "1 second ago", "in 1 second"),
+            (59, "59 seconds ago", "in 59 seconds"),
+            (60, "1 minute ago", "in 1 minute"),
+            (119, "1 minute ago", "in 1 minute"),
+            (120, "2 minutes ago", "in 2 minutes"),
+            (3_600, "1 hour ago", "in 1 hour"),
+            (86_399, "23 hours ago", "in 23 hours"),
+            (86_400, "1 day ago", "in 1 day"),
+            (2_592_000, "1 month ago", "in 1 month"),
+            (31_535_999, "12 months ago", "in 12 months"),
+            (31_536_000, "1 year ago", "in 1 year"),
+            (100 * 31_536_000, "100 years ago", "in 100 years"),
+        ] {
+            assert_eq!(format_relative(at(1_700_000_000 - offset), now), past, "{}", offset);
+            assert_eq!(format_relative(at(1_700_000_000 + offset), now), future, "{}", offset);
+        }
+        assert_eq!(format_relative(now + Duration::from_millis(999), now), "just now");
+    }
+
+    #[test]
+    fn times_format_with_strftime_specifiers() {
+        let time: SystemTime = at(1_709_251_199);
+        assert_eq!(format_time(time, "%Y-%m-%d %H:%M:%S"), "2024-02-29 23:59:59");
+        assert_eq!(format_time(time, "%a %b %e %y %s %% %q %"), "Thu Feb 29 24 1709251199 % %q %");
+        assert_eq!(format_time(at(-1), "%a %e %b %Y"), "Wed 31 Dec 1969");
+    }
+}
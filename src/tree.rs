@@ -1,175 +1,3481 @@
-use crate::config::Config;
-use crate::error::TreeResult;
-use crate::gitignore::GitignoreManager;
+use crate::checksum;
+use crate::collate;
+use crate::color;
+use crate::config::{Charset, ChecksumAlgorithm, ColorMode, DirSize, ExtGroupOrder, FsCase, IgnorePriority, Config, LineEnding, ReportFormat, SortField, SortKey, SymlinkLoopPolicy, TimeStyle, Wrap};
+use crate::diff::{self, DiffMark};
+use crate::encoding;
+use crate::exec::{self, ExecResult};
+use crate::error::{TreeError, TreeResult};
+use crate::filetype::SpecialKind;
+use crate::filter::EntryAttributes;
+use crate::git::{self, GitStatus};
+use crate::gitignore::{self, GitignoreManager, IgnoreMatch};
+use crate::info::{self, InfoFile};
+use crate::interactive;
+use crate::json::JsonValue;
+use crate::node::TreeNode;
+use crate::pattern::Patterns;
+use crate::size;
 use crate::stats::FileStats;
+use crate::terminal;
+use crate::time::{self, DateBound};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
-use std::io::Write;
-use std::path::Path;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often `--watch` takes the fingerprint of the listed entries; the
+/// `--watch` help states it
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Line width for `--columns` when neither `COLUMNS` nor a terminal gives one
+const DEFAULT_WIDTH: usize = 80;
+
+/// Spaces between two `--columns` columns
+const COLUMN_GAP: usize = 2;
+
+/// Entries looked at below a directory cut off by -L before --prune keeps it
+/// without knowing whether it holds a visible file
+const PROBE_LIMIT: usize = 10_000;
+
+/// Dim red for files older than `--stale-after`
+const STALE_COLOR: &str = "2;31";
+
+/// Prefix of names encoded by `--base64-names`
+const BASE64_MARKER: &str = "b64:";
+
+/// Window used by `--recent` when the boot time is unknown
+const RECENT_FALLBACK: Duration = Duration::from_secs(24 * 60 * 60);
 
 pub struct TreeGenerator<'a> {
     config: &'a Config,
     gitignore: GitignoreManager,
+    home_dir: Option<PathBuf>,
+    use_color: bool,
+    git_statuses: HashMap<PathBuf, GitStatus>,
+    duplicates: HashMap<PathBuf, usize>,
+    info_comments: HashMap<PathBuf, Vec<String>>,
+    /// Failures in the order they were met, for the summary
+    errors: Vec<EntryError>,
+    /// Where each path's failure is in `errors`, to mark it in the tree
+    error_index: HashMap<PathBuf, usize>,
+    listing_patterns: Patterns,
+    ignore_patterns: Patterns,
+    no_recurse_patterns: Patterns,
+    /// Line width for --columns, --compact-files and --info comments
+    width: usize,
+    /// The paths --watch polls for changes, from the last listing
+    watched: Vec<PathBuf>,
+    omitted: HashMap<PathBuf, Omitted>,
+    /// Files modified after this are tagged by --recent
+    recent_since: Option<SystemTime>,
+    /// Directory symlinks not followed because they loop
+    loops: HashSet<PathBuf>,
+    /// The -L limit of the root being built
+    max_depth: Option<usize>,
+    exec_results: HashMap<PathBuf, ExecResult>,
+    /// The line characters in use, with `auto` resolved by `generate`
+    charset: Charset,
+    /// Directories with nothing left to list after filtering
+    empty_dirs: HashSet<PathBuf>,
+    /// Directories listed but not descended into because of --no-recurse-into
+    unexpanded: HashSet<PathBuf>,
+    /// Directories listed as leaves because they exceed --skip-dirs-over
+    too_large: HashSet<PathBuf>,
+    /// Directory symlinks not followed because of --max-symlink-depth
+    link_limited: HashSet<PathBuf>,
+    /// Recursive content sizes found by --skip-dirs-over probes
+    dir_totals: HashMap<PathBuf, u64>,
+    /// State of the --shuffle generator, reset to the seed for every run
+    shuffle_state: u64,
+    /// Directories found by --mark-mounts to be mount points
+    mounts: HashSet<PathBuf>,
+    /// The root whose patterns are loaded while --interactive browses
+    browsed_root: Option<PathBuf>,
 }
 
 impl<'a> TreeGenerator<'a> {
     pub fn new(config: &'a Config) -> Self {
         Self {
             config,
-            gitignore: GitignoreManager::new(),
+            gitignore: GitignoreManager::new(config.strict_gitignore),
+            home_dir: if config.replace_home {
+                std::env::var_os("HOME").map(PathBuf::from)
+            } else {
+                None
+            },
+            use_color: match config.color {
+                ColorMode::Always => true,
+                ColorMode::Never => false,
+                ColorMode::Auto => {
+                    config.output.is_none() && config.output_dir.is_none() && std::io::stdout().is_terminal()
+                }
+            },
+            git_statuses: HashMap::new(),
+            duplicates: HashMap::new(),
+            info_comments: HashMap::new(),
+            errors: Vec::new(),
+            error_index: HashMap::new(),
+            // Compiled again for each root's case sensitivity in load_root
+            listing_patterns: compile_patterns(&config.match_patterns, config, false),
+            ignore_patterns: compile_patterns(&config.ignore_patterns, config, false),
+            no_recurse_patterns: compile_patterns(&config.no_recurse_into, config, false),
+            // Asking the terminal runs `stty`, so only when a layout needs it
+            width: if config.columns || config.compact_files || config.info {
+                terminal_width(config)
+            } else {
+                DEFAULT_WIDTH
+            },
+            watched: Vec::new(),
+            omitted: HashMap::new(),
+            recent_since: config.recent.then(|| {
+                config
+                    .recent_window
+                    .and_then(|window: Duration| SystemTime::now().checked_sub(window))
+                    .or_else(time::boot_time)
+                    .or_else(|| SystemTime::now().checked_sub(RECENT_FALLBACK))
+                    .unwrap_or(SystemTime::UNIX_EPOCH)
+            }),
+            loops: HashSet::new(),
+            max_depth: config.max_depth.first().copied(),
+            exec_results: HashMap::new(),
+            charset: config.charset,
+            empty_dirs: HashSet::new(),
+            unexpanded: HashSet::new(),
+            too_large: HashSet::new(),
+            link_limited: HashSet::new(),
+            dir_totals: HashMap::new(),
+            shuffle_state: 0,
+            mounts: HashSet::new(),
+            browsed_root: None,
         }
     }
 
     pub fn generate(&mut self) -> TreeResult<()> {
-        let mut tree_output: String = String::new();
+        // Box-drawing characters only reach a terminal with `auto`;
+        // --interactive always draws on one
+        if self.config.charset == Charset::Auto {
+            let to_terminal: bool = self.config.interactive
+                || (!self.writes_to_file() && self.config.output_dir.is_none() && std::io::stdout().is_terminal());
+            self.charset = if to_terminal { Charset::Utf8 } else { Charset::Ascii };
+        }
 
-        for path in &self.config.paths {
-            let mut path_stats: FileStats = FileStats::new();
+        if let Some(output_dir) = &self.config.output_dir {
+            return self.write_output_dir(output_dir);
+        }
 
-            if self.config.gitignore {
-                self.gitignore.load_patterns(path);
-            }
+        if self.config.interactive {
+            let config: &Config = self.config;
+            return interactive::run(self, config);
+        }
 
-            let path_tree: String = self.visit_dir(path, path, 0, &mut path_stats, "")?;
+        let tree_output: String = self.generate_output()?;
+        self.output_result(&tree_output)?;
 
-            tree_output.push_str(&path_tree);
-            tree_output.push_str(&format!(
-                "\n{} directories, {} files\n",
-                path_stats.dirs, path_stats.files
-            ));
+        if self.config.watch {
+            self.watch(tree_output)?;
         }
+        Ok(())
+    }
 
-        self.output_result(&tree_output)?;
+    fn generate_output(&mut self) -> TreeResult<String> {
+        let roots: Vec<(TreeNode, FileStats)> = self.build_roots()?;
+        if self.config.watch {
+            self.watched = watched_paths(self.config, &roots);
+        }
+        if let Some(stats_file) = &self.config.stats_file {
+            self.write_stats_file(stats_file, &roots)?;
+        }
+        self.render_roots(&roots)
+    }
+
+    /// Writes the --stats-file summary of all roots together
+    fn write_stats_file(&self, path: &Path, roots: &[(TreeNode, FileStats)]) -> TreeResult<()> {
+        let mut stats: FileStats = FileStats::new();
+        for (_, root_stats) in roots {
+            stats.add(root_stats);
+        }
+
+        let extensions: Vec<(String, JsonValue)> = sorted_ext_bytes(&stats)
+            .into_iter()
+            .map(|(ext, bytes): (&str, u64)| (ext.to_string(), JsonValue::Number(bytes)))
+            .collect();
+        let summary: JsonValue = JsonValue::object(vec![
+            ("directories", JsonValue::Number(stats.dirs as u64)),
+            ("files", JsonValue::Number(stats.files as u64)),
+            ("links", JsonValue::Number(stats.links as u64)),
+            ("hidden", JsonValue::Number(stats.hidden as u64)),
+            ("ignored", JsonValue::Number(stats.ignored as u64)),
+            ("bytes", JsonValue::Number(stats.bytes)),
+            ("size_by_ext", JsonValue::Object(extensions)),
+            ("errors", self.errors_value()),
+        ]);
+
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, format!("{}\n", summary.to_json(!self.config.json_compact)))?;
         Ok(())
     }
 
-    fn visit_dir(
-        &self,
-        dir: &Path,
-        base_dir: &Path,
-        level: usize,
-        stats: &mut FileStats,
-        prefix: &str,
-    ) -> TreeResult<String> {
-        let mut output: String = String::new();
+    /// Writes each root's tree to its own file inside `output_dir`
+    fn write_output_dir(&mut self, output_dir: &Path) -> TreeResult<()> {
+        let roots: Vec<(TreeNode, FileStats)> = self.build_roots()?;
+        if let Some(stats_file) = &self.config.stats_file {
+            self.write_stats_file(stats_file, &roots)?;
+        }
+        fs::create_dir_all(output_dir)?;
 
-        // Check max depth
-        if let Some(max_depth) = self.config.max_depth {
-            if level > max_depth {
-                return Ok(output);
+        let mut written: Vec<PathBuf> = Vec::new();
+        for root in &roots {
+            let stem: String = root_file_stem(&root.0.path);
+
+            // Roots sharing a name get a numeric suffix instead of overwriting
+            let mut file_path: PathBuf = output_dir.join(format!("{}.txt", stem));
+            let mut suffix: usize = 2;
+            while written.contains(&file_path) {
+                file_path = output_dir.join(format!("{}-{}.txt", stem, suffix));
+                suffix += 1;
             }
+
+            let content: String = self.render_roots(std::slice::from_ref(root))?;
+            fs::write(&file_path, self.finish_output(&content))?;
+            written.push(file_path);
         }
 
-        // Print directory name at level 0
-        if level == 0 {
-            let display_path: std::path::PathBuf = if self.config.full_path {
-                dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
-            } else {
-                dir.to_path_buf()
-            };
-            output.push_str(&format!("{}/\n", display_path.display()));
+        println!("{} files written to {}", written.len(), output_dir.display());
+        self.print_error_summary()
+    }
+
+    fn build_roots(&mut self) -> TreeResult<Vec<(TreeNode, FileStats)>> {
+        let config: &Config = self.config;
+        let mut roots: Vec<(TreeNode, FileStats)> = Vec::new();
+        self.git_statuses.clear();
+        self.duplicates.clear();
+        self.info_comments.clear();
+        self.errors.clear();
+        self.error_index.clear();
+        self.omitted.clear();
+        self.loops.clear();
+        self.exec_results.clear();
+        self.empty_dirs.clear();
+        self.unexpanded.clear();
+        self.link_limited.clear();
+        self.too_large.clear();
+        self.dir_totals.clear();
+        self.mounts.clear();
+        self.shuffle_state = config.shuffle.flatten().unwrap_or(0);
+
+        for (index, path) in config.paths.iter().enumerate() {
+            self.max_depth = self.depth_limit(index);
+            let started: Option<Instant> = config.debug.then(Instant::now);
+            let (mut root, mut path_stats) = self.build(path)?;
+
+            // Entries of the other tree are shown but never counted as files
+            if let Some(other) = &config.diff {
+                let (other_root, _) = self.build(other)?;
+                diff::merge(&mut root, other_root, config.diff_content, &|nodes: &mut Vec<TreeNode>| {
+                    self.sort_nodes(nodes)
+                });
+                for (_, node) in &root {
+                    match node.diff {
+                        Some(DiffMark::Added) => path_stats.added += 1,
+                        Some(DiffMark::Removed) => path_stats.removed += 1,
+                        Some(DiffMark::Modified) => path_stats.modified += 1,
+                        None => {}
+                    }
+                }
+            }
+
+            if config.color_by_git_status && self.use_color {
+                self.git_statuses.extend(git::status_map(path));
+            }
+
+            if config.duplicate_names {
+                path_stats.duplicate_groups = self.find_duplicates(&root);
+            }
+
+            if config.info {
+                self.load_info(&root);
+            }
+
+            // Collapsing only changes the display; the stats keep real counts
+            if config.collapse {
+                root.collapse_chains(None);
+            } else if let Some(flatten) = config.flatten {
+                root.collapse_chains(Some(flatten));
+            }
+
+            // Truncation works on what is displayed, so a collapsed chain
+            // counts as a single entry
+            if let Some(limit) = config.max_entries_total {
+                for (dir, count) in root.truncate_breadth_first(limit) {
+                    self.omitted.entry(dir).or_default().entries = count;
+                }
+            }
+
+            // Commands only run for the files that end up listed
+            if let Some(command) = &config.exec {
+                let files: Vec<PathBuf> = root
+                    .iter()
+                    .filter(|(_, node)| !node.is_removed() && node.path.is_file())
+                    .map(|(_, node)| node.path.clone())
+                    .collect();
+                let jobs: usize = config
+                    .exec_jobs
+                    .unwrap_or_else(|| thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+                self.exec_results.extend(exec::run_all(command, &files, jobs));
+            }
+
+            if let Some(started) = started {
+                self.debug(format_args!(
+                    "root {} done in {:.3} ms",
+                    path.display(),
+                    started.elapsed().as_secs_f64() * 1000.0
+                ));
+            }
+
+            roots.push((root, path_stats));
         }
 
-        let entries: fs::ReadDir = fs::read_dir(dir)?;
-        let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+        Ok(roots)
+    }
+
+    fn render_roots(&self, roots: &[(TreeNode, FileStats)]) -> TreeResult<String> {
+        if self.config.manifest {
+            Ok(self.render_manifest(roots))
+        } else if self.config.paths_only {
+            let tree_output: String = self.render_paths(roots);
+            self.check_memory(&tree_output)?;
+            Ok(tree_output)
+        } else if self.config.group_by_ext {
+            let tree_output: String = self.render_ext_groups(roots);
+            self.check_memory(&tree_output)?;
+            Ok(tree_output)
+        } else if self.config.json {
+            let tree_output: String = self.render_json(roots);
+            self.check_memory(&tree_output)?;
+            Ok(tree_output)
+        } else {
+            self.render_text(roots)
+        }
+    }
 
-        // Sort entries by name
-        entries.sort_by(|a: &fs::DirEntry, b: &fs::DirEntry| a.file_name().cmp(&b.file_name()));
+    /// Fails once the rendered output outgrows --max-memory
+    fn check_memory(&self, tree_output: &str) -> TreeResult<()> {
+        match self.config.max_memory {
+            Some(limit_mb) if tree_output.len() as u64 > limit_mb.saturating_mul(1024 * 1024) => {
+                Err(TreeError::MemoryLimit(limit_mb))
+            }
+            _ => Ok(()),
+        }
+    }
 
-        // Filter out entries based on config
-        entries.retain(|entry: &fs::DirEntry| self.should_include_entry(entry, base_dir));
+    /// Records how often each file's basename occurs under `root` for every
+    /// file sharing its name, returning the number of shared names
+    fn find_duplicates(&mut self, root: &TreeNode) -> usize {
+        let mut name_counts: HashMap<&str, usize> = HashMap::new();
+        for (_, node) in root {
+            if !node.is_dir && !node.is_removed() {
+                *name_counts.entry(node.name.as_str()).or_insert(0) += 1;
+            }
+        }
 
-        // Iterate through sorted entries
-        for (index, entry) in entries.iter().enumerate() {
-            let path: std::path::PathBuf = entry.path();
-            let file_name: std::ffi::OsString = entry.file_name();
-            let is_dir: bool = path.is_dir();
-            let is_last: bool = index == entries.len() - 1;
+        for (_, node) in root {
+            if let Some(&count) = name_counts.get(node.name.as_str())
+                && !node.is_dir
+                && !node.is_removed()
+                && count > 1
+            {
+                self.duplicates.insert(node.path.clone(), count);
+            }
+        }
 
-            // Calculate new prefix for child items
-            let (connector, new_prefix) = if self.config.no_indent {
-                ("", "")
-            } else if is_last {
-                ("└── ", "    ")
-            } else {
-                ("├── ", "│   ")
+        name_counts.values().filter(|&&count| count > 1).count()
+    }
+
+    /// Attaches the comments of the root's `.tree.info` to matching entries
+    fn load_info(&mut self, root: &TreeNode) {
+        let info_file: InfoFile = InfoFile::load(&root.path);
+        if info_file.is_empty() {
+            return;
+        }
+
+        for (depth, node) in root {
+            if depth == 0 || node.is_removed() {
+                continue;
+            }
+
+            let relative_path: String = node
+                .path
+                .strip_prefix(&root.path)
+                .unwrap_or(&node.path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if let Some(comment) = info_file.comment_for(&relative_path, &node.name, node.is_dir) {
+                self.info_comments.insert(node.path.clone(), comment.to_vec());
+            }
+        }
+    }
+
+    /// Polls the paths of the last listing and redraws once they change. Only
+    /// a fingerprint of their modification times and sizes is taken on each
+    /// poll; the tree is walked again when it differs and has stayed the same
+    /// for two consecutive polls, which debounces bursts of writes. Runs until
+    /// the process is interrupted.
+    fn watch(&mut self, mut last_output: String) -> TreeResult<()> {
+        let mut last_fingerprint: Vec<Option<(SystemTime, u64)>> = self.fingerprint();
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let mut fingerprint: Vec<Option<(SystemTime, u64)>> = self.fingerprint();
+            if fingerprint == last_fingerprint {
+                continue;
+            }
+
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+                let settled: Vec<Option<(SystemTime, u64)>> = self.fingerprint();
+                if settled == fingerprint {
+                    break;
+                }
+                fingerprint = settled;
+            }
+
+            let tree_output: String = self.generate_output()?;
+            last_fingerprint = self.fingerprint();
+            if tree_output == last_output {
+                continue;
+            }
+
+            if !self.writes_to_file() {
+                // Clear the screen and move the cursor home before redrawing
+                print!("\x1b[2J\x1b[H");
+            }
+            self.output_result(&tree_output)?;
+            last_output = tree_output;
+        }
+    }
+
+    /// The modification time and size of each watched path, `None` for one
+    /// that is gone
+    fn fingerprint(&self) -> Vec<Option<(SystemTime, u64)>> {
+        self.watched
+            .iter()
+            .map(|path: &PathBuf| {
+                let metadata: fs::Metadata = fs::symlink_metadata(path).ok()?;
+                Some((metadata.modified().ok()?, metadata.len()))
+            })
+            .collect()
+    }
+
+    /// Walks one root and returns its tree along with the collected statistics
+    pub fn build(&mut self, path: &Path) -> TreeResult<(TreeNode, FileStats)> {
+        let mut path_stats: FileStats = FileStats::new();
+        self.load_root(path);
+        let root: TreeNode = self.build_tree(path, &mut path_stats)?;
+        Ok((root, path_stats))
+    }
+
+    /// Loads the .gitignore patterns that apply below a root and matches -P,
+    /// -I and --no-recurse-into with the case sensitivity of its filesystem
+    fn load_root(&mut self, path: &Path) {
+        let ignore_case: bool = match self.config.fs_case {
+            FsCase::Sensitive => false,
+            FsCase::Insensitive => true,
+            FsCase::Auto => {
+                let insensitive: bool = is_case_insensitive_fs(path);
+                self.debug(format_args!(
+                    "{} is on a case-{} filesystem",
+                    path.display(),
+                    if insensitive { "insensitive" } else { "sensitive" }
+                ));
+                insensitive
+            }
+        };
+        self.listing_patterns = compile_patterns(&self.config.match_patterns, self.config, ignore_case);
+        self.ignore_patterns = compile_patterns(&self.config.ignore_patterns, self.config, ignore_case);
+        self.no_recurse_patterns = compile_patterns(&self.config.no_recurse_into, self.config, ignore_case);
+
+        if self.config.gitignore {
+            let repo_root: Option<PathBuf> = match &self.config.gitignore_root {
+                Some(gitignore_root) => gitignore_root.canonicalize().ok(),
+                None => gitignore::find_repo_root(path),
             };
+            self.gitignore.load_patterns(path, repo_root.as_deref());
+        }
+    }
 
-            // Create display name
-            let display_name: String = self.format_display_name(&path, &file_name, is_dir);
+    /// The -L limit of the root at `index` in the paths
+    fn depth_limit(&self, index: usize) -> Option<usize> {
+        // A single -L value covers every root
+        self.config.max_depth.get(index).or(self.config.max_depth.first()).copied()
+    }
 
-            // Add current entry to output
-            output.push_str(&format!("{}{}{}\n", prefix, connector, display_name));
+    /// Whether `list_children` reads anything for a directory `level`
+    /// directories below `root`, as the root's -L limit decides
+    pub fn lists_level(&self, root: &Path, level: usize) -> bool {
+        let index: Option<usize> = self.config.paths.iter().position(|path: &PathBuf| path == root);
+        !index
+            .and_then(|index: usize| self.depth_limit(index))
+            .is_some_and(|max_depth: usize| level > max_depth)
+    }
 
-            // Update statistics and recurse if directory
-            if is_dir {
-                stats.dirs += 1;
-                let child_prefix: String = format!("{}{}", prefix, new_prefix);
-                let child_output: String =
-                    self.visit_dir(&path, base_dir, level + 1, stats, &child_prefix)?;
-                output.push_str(&child_output);
+    /// The charset lines are drawn with, resolved for `auto` by `generate`
+    pub fn charset(&self) -> Charset {
+        self.charset
+    }
+
+    /// Reads the listed entries of `dir`, which lies `level` directories
+    /// below `root`, without descending into them. --interactive calls this
+    /// as directories are expanded, so all filters and sorting still apply.
+    pub fn list_children(&mut self, root: &Path, dir: &Path, level: usize) -> TreeResult<Vec<TreeNode>> {
+        if self.browsed_root.as_deref() != Some(root) {
+            self.load_root(root);
+            if let Some(index) = self.config.paths.iter().position(|path: &PathBuf| path == root) {
+                self.max_depth = self.depth_limit(index);
+            }
+            // Nested .gitignore files above `dir` were read by earlier
+            // expansions of the previous patterns; read them again
+            let mut ancestors: Vec<&Path> = dir.ancestors().skip(1).take(level.saturating_sub(1)).collect();
+            ancestors.reverse();
+            for ancestor in ancestors {
+                self.gitignore.enter_dir(ancestor);
+            }
+            self.browsed_root = Some(root.to_path_buf());
+        }
+
+        let mut stats: FileStats = FileStats::new();
+        let entries: Vec<fs::DirEntry> = self.read_entries_or_record(dir, root, level, &mut stats)?;
+        Ok(entries
+            .into_iter()
+            .map(|entry: fs::DirEntry| {
+                let path: PathBuf = entry.path();
+                let size: Option<u64> =
+                    (self.shows_sizes() && !self.config.no_metadata).then(|| self.entry_size_or_record(&path));
+                let mut node: TreeNode =
+                    TreeNode::new(entry.file_name().to_string_lossy().to_string(), path, entry_is_dir(&entry));
+                node.is_symlink = entry.file_type().is_ok_and(|t: fs::FileType| t.is_symlink());
+                node.special = entry.file_type().ok().and_then(SpecialKind::of);
+                node.size = size;
+                node
+            })
+            .collect())
+    }
+
+    /// The name of a node as the tree shows it, markers and metadata included
+    pub fn display_name(&self, node: &TreeNode) -> String {
+        self.format_display_name(node)
+    }
+
+    fn build_tree(&mut self, path: &Path, stats: &mut FileStats) -> TreeResult<TreeNode> {
+        let children: Vec<TreeNode> = self.visit_dir(path, path, stats)?;
+        let mut root: TreeNode = TreeNode::new(path.display().to_string(), path.to_path_buf(), true);
+        root.children = children;
+        Ok(root)
+    }
+
+    fn render_text(&self, roots: &[(TreeNode, FileStats)]) -> TreeResult<String> {
+        let mut tree_output: String = String::new();
+
+        if self.config.separate_reports {
+            for (index, (root, path_stats)) in roots.iter().enumerate() {
+                if index > 0 && (self.config.blank_between_roots || self.config.root_separator.is_some()) {
+                    tree_output.push('\n');
+                    if let Some(rule) = &self.config.root_separator {
+                        tree_output.push_str(&format!("{}\n\n", rule));
+                    }
+                }
+                if !self.config.entries_only {
+                    tree_output.push_str(&format!("{}\n", self.format_root_name(&root.path)));
+                }
+                self.render_children(&root.children, self.omitted_count(&root.path), "", 0, &mut tree_output);
+                tree_output.push_str(&self.format_report(path_stats));
+                self.check_memory(&tree_output)?;
+            }
+        } else if self.config.entries_only {
+            // Without root headers the children of all roots form one listing
+            let mut total_stats: FileStats = FileStats::new();
+            let entries: Vec<TreeNode> = roots
+                .iter()
+                .flat_map(|(root, _)| root.children.iter().cloned())
+                .collect();
+
+            let omitted: Omitted = roots
+                .iter()
+                .fold(Omitted::default(), |total: Omitted, (root, _)| total.add(self.omitted_count(&root.path)));
+            self.render_children(&entries, omitted, "", 0, &mut tree_output);
+            self.check_memory(&tree_output)?;
+            for (_, path_stats) in roots {
+                total_stats.add(path_stats);
+            }
+
+            tree_output.push_str(&self.format_report(&total_stats));
+        } else {
+            // Render every root as a child of one virtual root with a single report
+            let mut total_stats: FileStats = FileStats::new();
+
+            for (index, (root, path_stats)) in roots.iter().enumerate() {
+                let is_last: bool = index == roots.len() - 1;
+                let (connector, new_prefix) = self.connectors(is_last);
+
+                tree_output.push_str(&format!("{}{}\n", connector, self.format_root_name(&root.path)));
+                let omitted: Omitted = self.omitted_count(&root.path);
+                self.render_children(&root.children, omitted, new_prefix, 0, &mut tree_output);
+                self.check_memory(&tree_output)?;
+
+                total_stats.add(path_stats);
+                total_stats.dirs += 1;
+            }
+
+            tree_output.push_str(&self.format_report(&total_stats));
+        }
+
+        tree_output.push_str(&self.format_legend());
+        Ok(tree_output)
+    }
+
+    /// The --legend key for the color schemes in use, empty without colors
+    fn format_legend(&self) -> String {
+        if !self.config.legend || !self.use_color {
+            return String::new();
+        }
+
+        let mut lines: Vec<String> = Vec::new();
+        if self.config.depth_colors {
+            let levels: Vec<String> = (0..color::DEPTH_PALETTE.len())
+                .map(|level: usize| color::paint(&format!("level {}", level + 1), color::depth_color(level)))
+                .collect();
+            lines.push(format!("  Indentation: {}, then repeating", levels.join(" ")));
+        }
+        if self.config.color_by_git_status {
+            let statuses: Vec<String> = [GitStatus::Untracked, GitStatus::Modified, GitStatus::Staged]
+                .iter()
+                .map(|status: &GitStatus| color::paint(status.label(), status.color()))
+                .collect();
+            lines.push(format!("  Git status: {}", statuses.join(" ")));
+        }
+        if self.config.diff.is_some() {
+            let marks: Vec<String> = [DiffMark::Added, DiffMark::Removed, DiffMark::Modified]
+                .iter()
+                .map(|mark: &DiffMark| color::paint(&format!("{} {}", mark.marker(), mark.label()), mark.color()))
+                .collect();
+            lines.push(format!("  Diff: {}", marks.join(" ")));
+        }
+        if self.config.stale_after.is_some() && !self.config.no_metadata {
+            lines.push(format!("  {}", color::paint("stale: not modified within --stale-after", STALE_COLOR)));
+        }
+
+        if lines.is_empty() {
+            return String::new();
+        }
+        format!("\nLegend:\n{}\n", lines.join("\n"))
+    }
+
+    fn render_json(&self, roots: &[(TreeNode, FileStats)]) -> String {
+        let mut total_stats: FileStats = FileStats::new();
+        let mut items: Vec<JsonValue> = Vec::new();
+
+        for (root, path_stats) in roots {
+            items.push(self.json_node(root, true));
+            total_stats.add(path_stats);
+        }
+
+        if self.config.noreport {
+            return format!("{}\n", JsonValue::Array(items).to_json(!self.config.json_compact));
+        }
+
+        let mut report: Vec<(&str, JsonValue)> = vec![
+            ("type", JsonValue::string("report")),
+            ("directories", JsonValue::Number(total_stats.dirs as u64)),
+            ("files", JsonValue::Number(total_stats.files as u64)),
+        ];
+        if self.config.no_dereference_report {
+            report.push(("links", JsonValue::Number(total_stats.links as u64)));
+        }
+        if self.config.show_hidden_summary && !self.config.all {
+            report.push(("hidden", JsonValue::Number(total_stats.hidden as u64)));
+        }
+        if self.config.diff.is_some() {
+            report.push(("added", JsonValue::Number(total_stats.added as u64)));
+            report.push(("removed", JsonValue::Number(total_stats.removed as u64)));
+        }
+        if self.config.diff_content {
+            report.push(("modified", JsonValue::Number(total_stats.modified as u64)));
+        }
+        if self.config.duplicate_names {
+            report.push(("duplicates", JsonValue::Number(total_stats.duplicate_groups as u64)));
+        }
+        items.push(JsonValue::object(report));
+
+        let mut output: String = JsonValue::Array(items).to_json(!self.config.json_compact);
+        output.push('\n');
+        output
+    }
+
+    /// Lists the files of all roots under one heading per extension; the
+    /// directories only contribute their files
+    fn render_ext_groups(&self, roots: &[(TreeNode, FileStats)]) -> String {
+        let mut groups: HashMap<String, Vec<&TreeNode>> = HashMap::new();
+        let mut total_stats: FileStats = FileStats::new();
+        for (root, path_stats) in roots {
+            for (_, node) in root {
+                if !node.is_dir {
+                    let extension: String = node
+                        .path
+                        .extension()
+                        .map(|ext| ext.to_string_lossy().to_lowercase())
+                        .unwrap_or_default();
+                    groups.entry(extension).or_default().push(node);
+                }
+            }
+            total_stats.add(path_stats);
+        }
+
+        let mut groups: Vec<(String, Vec<&TreeNode>)> = groups.into_iter().collect();
+        match self.config.ext_group_order {
+            ExtGroupOrder::Name => groups.sort_by(|a, b| a.0.cmp(&b.0)),
+            ExtGroupOrder::Count => groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0))),
+        }
+
+        let mut output: String = String::new();
+        for (index, (extension, files)) in groups.iter().enumerate() {
+            if index > 0 {
+                output.push('\n');
+            }
+            if extension.is_empty() {
+                output.push_str("== (no extension) ==\n");
             } else {
-                stats.files += 1;
+                output.push_str(&format!("== .{} ==\n", extension));
+            }
+            for node in files {
+                output.push_str(&format!("{}\n", self.format_display_path(node)));
             }
         }
 
-        Ok(output)
+        output.push_str(&self.format_report(&total_stats));
+        output
     }
 
-    fn should_include_entry(&self, entry: &fs::DirEntry, base_dir: &Path) -> bool {
-        let path: std::path::PathBuf = entry.path();
-        let file_name: std::ffi::OsString = entry.file_name();
-        let is_dir: bool = path.is_dir();
+    /// The path of a file with the metadata and markers of its tree line
+    fn format_display_path(&self, node: &TreeNode) -> String {
+        let mut named: TreeNode = node.clone();
+        let path: PathBuf = self.display_path(&node.path);
+        named.name = if self.config.base64_names {
+            path.to_string_lossy().to_string()
+        } else {
+            self.replace_home(&path)
+        };
+        self.format_display_name(&named)
+    }
 
-        // Skip hidden files unless -a flag is provided
-        if !self.config.all && file_name.to_string_lossy().starts_with('.') {
-            return false;
+    /// The part of the path behind a displayed name, so that names that are
+    /// not valid UTF-8 survive --base64-names. A collapsed chain or a path
+    /// name covers as many trailing components as it has parts.
+    fn raw_name(&self, node: &TreeNode) -> PathBuf {
+        if self.config.full_path {
+            return node.path.canonicalize().unwrap_or_else(|_| node.path.clone());
         }
 
-        // Skip files if -d flag is provided
-        if self.config.dirs_only && !is_dir {
-            return false;
+        let parts: usize = node.name.split('/').count();
+        match node.path.ancestors().nth(parts) {
+            Some(base) => node.path.strip_prefix(base).unwrap_or(&node.path).to_path_buf(),
+            None => node.path.clone(),
         }
+    }
 
-        // Skip .git directory if gitignore option is used
-        if self.config.gitignore && path == base_dir.join(".git") {
-            return false;
+    /// Hashes every listed entry in display order into one SHA-256 digest.
+    /// Each entry contributes its depth, its type, its name and, for files,
+    /// its size plus its content digest when --checksum is given. Root paths
+    /// and timestamps are left out, so an unchanged tree hashes the same
+    /// wherever it lives.
+    fn render_manifest(&self, roots: &[(TreeNode, FileStats)]) -> String {
+        let mut hasher: checksum::Hasher = checksum::Hasher::new(ChecksumAlgorithm::Sha256);
+
+        for (root, _) in roots {
+            for (depth, node) in root {
+                if depth == 0 {
+                    hasher.update(b"root\n");
+                    continue;
+                }
+
+                let mut record: String = format!("{}\0{}\0", depth, if node.is_dir { 'd' } else { 'f' });
+                record.push_str(&node.name);
+                if !node.is_dir {
+                    let size: u64 = fs::metadata(&node.path).map(|m: fs::Metadata| m.len()).unwrap_or(0);
+                    record.push_str(&format!("\0{}", size));
+                    if let Some(digest) = self.file_checksum(node) {
+                        record.push_str(&format!("\0{}", digest));
+                    }
+                }
+                record.push('\n');
+                hasher.update(record.as_bytes());
+            }
         }
 
-        // Check gitignore patterns
-        if self.config.gitignore && self.gitignore.matches(&path, base_dir) {
-            return false;
+        format!("{}\n", hasher.finish())
+    }
+
+    /// Lists the path of every entry below the roots, one per line
+    fn render_paths(&self, roots: &[(TreeNode, FileStats)]) -> String {
+        let entries: Vec<(PathBuf, bool)> = roots
+            .iter()
+            .flat_map(|(root, _)| root)
+            .filter(|&(depth, _)| depth > 0)
+            .map(|(_, node)| (self.display_path(&node.path), node.is_dir))
+            .collect();
+
+        let mut output: String = String::new();
+        let base: Option<PathBuf> = self
+            .config
+            .trim_common_prefix
+            .then(|| common_dir_prefix(entries.iter().map(|(path, _)| path.as_path())))
+            .flatten();
+        if let Some(base) = &base {
+            output.push_str(&format!("base: {}\n", self.with_separator(self.replace_home(base))));
         }
 
-        true
+        for (path, is_dir) in &entries {
+            let shown: String = match &base {
+                Some(base) => path.strip_prefix(base).unwrap_or(path).display().to_string(),
+                None => self.replace_home(path),
+            };
+            // The trailing slash of a directory is a separator here too
+            let suffix: &str = if *is_dir { self.dir_suffix() } else { "" };
+            output.push_str(&format!("{}\n", self.with_separator(format!("{}{}", shown, suffix))));
+        }
+        output
     }
 
-    fn format_display_name(
-        &self,
-        path: &Path,
-        file_name: &std::ffi::OsStr,
-        is_dir: bool,
-    ) -> String {
+    fn json_node(&self, root: &TreeNode, is_root: bool) -> JsonValue {
+        // Built bottom-up: a frame holds the values of the children done so far
+        let mut stack: Vec<(&TreeNode, Vec<JsonValue>)> = vec![(root, Vec::new())];
+        loop {
+            let (node, contents) = stack.last_mut().expect("the root frame is popped last");
+            let node: &TreeNode = node;
+            if let Some(child) = node.children.get(contents.len()) {
+                stack.push((child, Vec::new()));
+                continue;
+            }
+
+            let (node, contents) = stack.pop().expect("the root frame is popped last");
+            let value: JsonValue = self.json_entry(node, is_root && stack.is_empty(), contents);
+            match stack.last_mut() {
+                Some((_, siblings)) => siblings.push(value),
+                None => return value,
+            }
+        }
+    }
+
+    /// The JSON object of one entry, given the values of its children
+    fn json_entry(&self, node: &TreeNode, is_root: bool, mut contents: Vec<JsonValue>) -> JsonValue {
+        let name: String = if self.config.full_path {
+            self.replace_home(&self.display_path(&node.path))
+        } else if is_root {
+            self.display_path(&node.path).display().to_string()
+        } else {
+            node.name.clone()
+        };
+
+        let node_type: &str = if node.is_dir { "directory" } else { "file" };
+        let mut fields: Vec<(&str, JsonValue)> = vec![
+            ("type", JsonValue::string(node_type)),
+            ("name", JsonValue::String(name)),
+        ];
+
+        if let Some(digest) = self.file_checksum(node) {
+            fields.push(("checksum", JsonValue::String(digest)));
+        }
+        if let Some(size) = node.size {
+            fields.push(("size", JsonValue::Number(size)));
+        }
+        if let Some(date) = self.format_date(node) {
+            fields.push(("time", JsonValue::String(date)));
+        }
+        if let Some(mark) = node.diff {
+            fields.push(("diff", JsonValue::string(mark.label())));
+        }
+        if let Some(&count) = self.duplicates.get(&node.path) {
+            fields.push(("duplicates", JsonValue::Number(count as u64)));
+        }
+        if let Some(error) = self.entry_error(&node.path) {
+            fields.push(("error", JsonValue::String(error.message.clone())));
+        }
+        if let Some(comment) = self.info_comments.get(&node.path) {
+            fields.push(("info", JsonValue::String(comment.join("\n"))));
+        }
+        if let Some(result) = self.exec_results.get(&node.path) {
+            if let Some(code) = result.code {
+                fields.push(("exit", JsonValue::Number(u64::from(code.unsigned_abs()))));
+            }
+            if self.config.exec_output {
+                fields.push(("output", JsonValue::String(result.output.clone())));
+            }
+        }
+
+        if node.is_dir {
+            let omitted: Omitted = self.omitted_count(&node.path);
+            if omitted.files > 0 {
+                contents.push(JsonValue::object(vec![
+                    ("type", JsonValue::string("more")),
+                    ("files", JsonValue::Number(omitted.files as u64)),
+                ]));
+            }
+            if omitted.entries > 0 {
+                contents.push(JsonValue::object(vec![
+                    ("type", JsonValue::string("more")),
+                    ("count", JsonValue::Number(omitted.entries as u64)),
+                ]));
+            }
+            fields.push(("contents", JsonValue::Array(contents)));
+        }
+
+        JsonValue::object(fields)
+    }
+
+    /// The path a root or an entry is shown as by every output mode:
+    /// canonical with -f, and with --deterministic never an absolute path
+    /// specific to this machine. An absolute root then shows as its last
+    /// component and the entries below it as paths from there.
+    fn display_path(&self, path: &Path) -> PathBuf {
         if self.config.full_path {
-            let full_path: std::path::PathBuf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
-            full_path.to_string_lossy().to_string()
+            return path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        }
+        if !self.config.deterministic || !path.is_absolute() {
+            return path.to_path_buf();
+        }
+
+        let root: Option<&PathBuf> = self
+            .config
+            .paths
+            .iter()
+            .filter(|root: &&PathBuf| path.starts_with(root))
+            .max_by_key(|root: &&PathBuf| root.components().count());
+        let relative: Option<&Path> = root
+            .map(|root: &PathBuf| root.parent().unwrap_or(root))
+            .and_then(|base: &Path| path.strip_prefix(base).ok())
+            .filter(|relative: &&Path| !relative.as_os_str().is_empty());
+        match relative {
+            Some(relative) => relative.to_path_buf(),
+            None => path.file_name().map_or_else(|| path.to_path_buf(), PathBuf::from),
+        }
+    }
+
+    fn format_root_name(&self, dir: &Path) -> String {
+        let display_path: PathBuf = self.display_path(dir);
+        let mut root_name: String = if self.config.base64_names {
+            format!("{}{}{}", BASE64_MARKER, encoding::base64(&path_bytes(&display_path)), self.dir_suffix())
+        } else if self.config.full_path {
+            format!("{}{}", self.with_separator(self.replace_home(&display_path)), self.dir_suffix())
         } else {
-            let name: String = if is_dir {
-                format!("{}/", file_name.to_string_lossy())
-            } else {
-                file_name.to_string_lossy().to_string()
-            };
-            name
+            format!("{}{}", self.replace_home(&display_path), self.dir_suffix())
+        };
+        if let Some(error) = self.entry_error(dir) {
+            root_name.push_str(&format!(" [{}]", error.marker));
+        }
+
+        match &self.config.tree_root_symbol {
+            Some(symbol) => symbol.replace("{}", &root_name),
+            None => root_name,
         }
     }
 
-    fn output_result(&self, content: &str) -> TreeResult<()> {
-        if let Some(output_path) = &self.config.output {
-            let mut file: fs::File = fs::File::create(output_path)?;
-            file.write_all(content.as_bytes())?;
-            println!("Tree output generated successfully.");
+    fn format_report(&self, stats: &FileStats) -> String {
+        if self.config.noreport {
+            return String::new();
+        }
+
+        if self.config.report_format == ReportFormat::Json {
+            let mut fields: Vec<(&str, JsonValue)> = vec![
+                ("directories", JsonValue::Number(stats.dirs as u64)),
+                ("files", JsonValue::Number(stats.files as u64)),
+                ("bytes", JsonValue::Number(stats.bytes)),
+            ];
+            if self.config.no_dereference_report {
+                fields.push(("links", JsonValue::Number(stats.links as u64)));
+            }
+            if self.config.show_hidden_summary && !self.config.all {
+                fields.push(("hidden", JsonValue::Number(stats.hidden as u64)));
+            }
+            if self.config.show_ignored_count {
+                fields.push(("ignored", JsonValue::Number(stats.ignored as u64)));
+            }
+            if self.config.diff.is_some() {
+                fields.push(("added", JsonValue::Number(stats.added as u64)));
+                fields.push(("removed", JsonValue::Number(stats.removed as u64)));
+            }
+            if self.config.diff_content {
+                fields.push(("modified", JsonValue::Number(stats.modified as u64)));
+            }
+            if self.config.duplicate_names {
+                fields.push(("duplicates", JsonValue::Number(stats.duplicate_groups as u64)));
+            }
+            if self.config.per_level_stats {
+                let levels: Vec<JsonValue> = stats
+                    .levels
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &(dirs, files))| {
+                        JsonValue::object(vec![
+                            ("level", JsonValue::Number(index as u64 + 1)),
+                            ("directories", JsonValue::Number(dirs as u64)),
+                            ("files", JsonValue::Number(files as u64)),
+                        ])
+                    })
+                    .collect();
+                fields.push(("levels", JsonValue::Array(levels)));
+            }
+            if self.config.size_by_ext {
+                let extensions: Vec<(String, JsonValue)> = sorted_ext_bytes(stats)
+                    .into_iter()
+                    .map(|(ext, bytes): (&str, u64)| (ext.to_string(), JsonValue::Number(bytes)))
+                    .collect();
+                fields.push(("size_by_ext", JsonValue::Object(extensions)));
+            }
+            return format!("\n{}\n", JsonValue::object(fields).to_json(false));
+        }
+
+        let mut report: String = if self.config.du && (self.config.report_human || self.config.si) {
+            let total: String = self.human_size(stats.bytes);
+            format!("\n{} used in {} directories, {} files", total, stats.dirs, stats.files)
+        } else if self.config.du {
+            format!("\n{} bytes used in {} directories, {} files", stats.bytes, stats.dirs, stats.files)
         } else {
-            print!("{}", content);
+            format!("\n{} directories, {} files", stats.dirs, stats.files)
+        };
+        if self.config.no_dereference_report {
+            report.push_str(&format!(", {} links", stats.links));
         }
-        Ok(())
+        if self.config.show_hidden_summary && !self.config.all {
+            report.push_str(&format!(" ({} hidden)", stats.hidden));
+        }
+        if self.config.show_ignored_count {
+            report.push_str(&format!(" ({} ignored)", stats.ignored));
+        }
+        if self.config.diff.is_some() {
+            report.push_str(&format!(", {} added, {} removed", stats.added, stats.removed));
+        }
+        if self.config.diff_content {
+            report.push_str(&format!(", {} modified", stats.modified));
+        }
+        if self.config.duplicate_names {
+            report.push_str(&format!(", {} duplicate names", stats.duplicate_groups));
+        }
+        report.push('\n');
+        if self.config.per_level_stats && !stats.levels.is_empty() {
+            let levels: Vec<String> = stats
+                .levels
+                .iter()
+                .enumerate()
+                .map(|(index, (dirs, files))| format!("Level {}: {} dirs, {} files", index + 1, dirs, files))
+                .collect();
+            report.push_str(&format!("{}\n", levels.join(" / ")));
+        }
+        if self.config.size_by_ext && !stats.ext_bytes.is_empty() {
+            let extensions: Vec<String> = sorted_ext_bytes(stats)
+                .into_iter()
+                .map(|(ext, bytes): (&str, u64)| {
+                    let label: String = if ext.is_empty() { "(none)".to_string() } else { format!(".{}", ext) };
+                    format!("{}: {}", label, self.human_size(bytes))
+                })
+                .collect();
+            report.push_str(&format!("{}\n", extensions.join(", ")));
+        }
+        report
+    }
+
+    /// Builds the children of `dir`. The walk keeps its pending directories on
+    /// an explicit stack so arbitrarily deep trees cannot overflow the native
+    /// stack; entries still come out in the same order as a recursive walk.
+    fn visit_dir(
+        &mut self,
+        dir: &Path,
+        base_dir: &Path,
+        stats: &mut FileStats,
+    ) -> TreeResult<Vec<TreeNode>> {
+        let mut stack: Vec<DirFrame> = vec![DirFrame {
+            entries: self.read_entries_or_record(dir, base_dir, 0, stats)?.into_iter(),
+            level: 0,
+            canonical: dir.canonicalize().ok(),
+            symlink_hops: 0,
+            nodes: Vec::new(),
+            pending: None,
+        }];
+
+        loop {
+            let frame: &mut DirFrame = stack.last_mut().expect("traversal stack is never empty");
+
+            let Some(entry) = frame.entries.next() else {
+                // Every entry of this directory is done: attach it to its parent
+                let mut frame: DirFrame = stack.pop().expect("traversal stack is never empty");
+                let Some(mut node) = frame.pending else {
+                    self.cap_files(dir, &mut frame.nodes);
+                    return Ok(frame.nodes);
+                };
+
+                // Drop empty branches once their subtree is known. A directory
+                // cut off by -L is only kept if a visible file lies somewhere
+                // below it.
+                if (self.config.prune || self.config.only_matching_ancestors)
+                    && frame.nodes.is_empty()
+                    && !(self.exceeds_max_depth(frame.level) && self.has_visible_files(&node.path, base_dir))
+                {
+                    continue;
+                }
+
+                // --show-ignored-only walks every directory but only keeps the
+                // ignored ones and those leading to ignored entries
+                if self.config.show_ignored_only
+                    && frame.nodes.is_empty()
+                    && !self.gitignore.matches(&node.path, base_dir, true)
+                {
+                    continue;
+                }
+
+                self.count_entry(&node, frame.level, base_dir, stats);
+
+                // A directory cut off by -L was never read, so it is not known to be empty
+                if self.config.mark_empty_dirs && frame.nodes.is_empty() && !self.exceeds_max_depth(frame.level) {
+                    self.empty_dirs.insert(node.path.clone());
+                }
+
+                // Roll the listed contents up into the directory size; like
+                // `du`, --du also counts the blocks of the directory itself
+                if self.config.du || self.config.dir_size == DirSize::Recursive {
+                    let own_size: u64 = if self.config.du { node.size.unwrap_or(0) } else { 0 };
+                    node.size = Some(
+                        own_size + frame.nodes.iter().filter_map(|child: &TreeNode| child.size).sum::<u64>(),
+                    );
+                }
+
+                self.cap_files(&node.path, &mut frame.nodes);
+                node.children = frame.nodes;
+                let parent: &mut DirFrame = stack.last_mut().expect("a pending directory has a parent");
+                parent.nodes.push(node);
+                continue;
+            };
+
+            let path: std::path::PathBuf = entry.path();
+            let name: String = entry.file_name().to_string_lossy().to_string();
+            let is_symlink: bool = entry.file_type().is_ok_and(|t: fs::FileType| t.is_symlink());
+            let size: Option<u64> =
+                (self.shows_sizes() && !self.config.no_metadata).then(|| self.entry_size_or_record(&path));
+
+            // Descend into directories; their node is finished when popped
+            if entry_is_dir(&entry) {
+                let level: usize = frame.level + 1;
+                let symlink_hops: usize = frame.symlink_hops + usize::from(is_symlink);
+                // A real directory resolves to its parent's canonical path
+                // plus its name, so only symlinks cost a lookup
+                let canonical: Option<PathBuf> = if is_symlink {
+                    path.canonicalize().ok()
+                } else {
+                    frame.canonical.as_ref().map(|parent: &PathBuf| parent.join(entry.file_name()))
+                };
+
+                // A symlink into another filesystem is not a mount point
+                if self.config.mark_mounts && !is_symlink && is_mount_point(&path) {
+                    self.mounts.insert(path.clone());
+                }
+
+                // A symlink back to a directory being walked would never end
+                if is_symlink
+                    && canonical.is_some()
+                    && stack.iter().any(|ancestor: &DirFrame| ancestor.canonical == canonical)
+                {
+                    match self.config.symlink_loop_policy {
+                        SymlinkLoopPolicy::Error => return Err(TreeError::SymlinkLoop(path)),
+                        SymlinkLoopPolicy::Skip => continue,
+                        SymlinkLoopPolicy::Mark => {
+                            let mut node: TreeNode = TreeNode::new(name, path, true);
+                            node.is_symlink = is_symlink;
+                            node.size = size;
+                            self.count_entry(&node, level, base_dir, stats);
+                            self.loops.insert(node.path.clone());
+                            let frame: &mut DirFrame = stack.last_mut().expect("traversal stack is never empty");
+                            frame.nodes.push(node);
+                            continue;
+                        }
+                    }
+                }
+
+                // Chains of directory symlinks are only followed so far, even
+                // when they never loop
+                if symlink_hops > self.config.max_symlink_depth {
+                    let mut node: TreeNode = TreeNode::new(name, path, true);
+                    node.is_symlink = is_symlink;
+                    node.size = size;
+                    self.count_entry(&node, level, base_dir, stats);
+                    self.link_limited.insert(node.path.clone());
+                    let frame: &mut DirFrame = stack.last_mut().expect("traversal stack is never empty");
+                    frame.nodes.push(node);
+                    continue;
+                }
+
+                // Directories matching --no-recurse-into are listed as leaves
+                if self.no_recurse_patterns.matches(&name) {
+                    let mut node: TreeNode = TreeNode::new(name, path, true);
+                    node.is_symlink = is_symlink;
+                    node.size = size;
+                    self.count_entry(&node, level, base_dir, stats);
+                    self.unexpanded.insert(node.path.clone());
+                    let frame: &mut DirFrame = stack.last_mut().expect("traversal stack is never empty");
+                    frame.nodes.push(node);
+                    continue;
+                }
+
+                // Directories above --skip-dirs-over are listed as leaves;
+                // their probed contents still count toward the byte totals
+                if let Some(threshold) = self.config.skip_dirs_over
+                    && !self.exceeds_max_depth(level)
+                {
+                    let total: u64 = self.dir_total(&path);
+                    if total > threshold {
+                        let mut node: TreeNode = TreeNode::new(name, path, true);
+                        node.is_symlink = is_symlink;
+                        node.size = size.map(|own: u64| match (self.config.du, self.config.dir_size) {
+                            (true, _) => own + total,
+                            (false, DirSize::Recursive) => total,
+                            (false, DirSize::Entry) => own,
+                        });
+                        self.count_entry(&node, level, base_dir, stats);
+                        if self.tracks_bytes() {
+                            stats.bytes += total;
+                        }
+                        self.too_large.insert(node.path.clone());
+                        let frame: &mut DirFrame = stack.last_mut().expect("traversal stack is never empty");
+                        frame.nodes.push(node);
+                        continue;
+                    }
+                }
+
+                let entries: Vec<fs::DirEntry> = self.read_entries_or_record(&path, base_dir, level, stats)?;
+                let mut pending: TreeNode = TreeNode::new(name, path, true);
+                pending.is_symlink = is_symlink;
+                pending.size = size;
+                stack.push(DirFrame {
+                    entries: entries.into_iter(),
+                    level,
+                    canonical,
+                    symlink_hops,
+                    nodes: Vec::new(),
+                    pending: Some(pending),
+                });
+                continue;
+            }
+
+            // A dangling link keeps its own type
+            let file_type: std::io::Result<fs::FileType> = if !self.config.no_dereference_files && !self.config.no_metadata {
+                fs::metadata(&path)
+                    .map(|metadata: fs::Metadata| metadata.file_type())
+                    .or_else(|_| entry.file_type())
+            } else {
+                entry.file_type()
+            };
+            let mut node: TreeNode = TreeNode::new(name, path, false);
+            node.is_symlink = is_symlink;
+            node.special = file_type.ok().and_then(SpecialKind::of);
+            node.size = size;
+            self.count_entry(&node, frame.level + 1, base_dir, stats);
+            frame.nodes.push(node);
+        }
+    }
+
+    /// Drops the files beyond --max-files-per-dir from a directory's nodes.
+    /// They are already counted, so the report and sizes stay complete.
+    fn cap_files(&mut self, dir: &Path, nodes: &mut Vec<TreeNode>) {
+        let Some(limit) = self.config.max_files_per_dir else {
+            return;
+        };
+
+        let mut files: usize = 0;
+        let before: usize = nodes.len();
+        nodes.retain(|node: &TreeNode| {
+            if node.is_dir {
+                return true;
+            }
+            files += 1;
+            files <= limit
+        });
+
+        if nodes.len() < before {
+            self.omitted.entry(dir.to_path_buf()).or_default().files = before - nodes.len();
+        }
+    }
+
+    /// Like `read_entries`, but with --ignore-errors an unreadable directory
+    /// is recorded and listed as empty instead of aborting the walk
+    fn read_entries_or_record(
+        &mut self,
+        dir: &Path,
+        base_dir: &Path,
+        level: usize,
+        stats: &mut FileStats,
+    ) -> TreeResult<Vec<fs::DirEntry>> {
+        match self.read_entries(dir, base_dir, level, stats) {
+            Err(TreeError::Io(err)) if self.config.ignore_errors => {
+                self.record_error(dir, &err);
+                Ok(Vec::new())
+            }
+            result => result,
+        }
+    }
+
+    fn record_error(&mut self, path: &Path, err: &std::io::Error) {
+        self.push_error(EntryError {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+            marker: "error opening dir",
+        });
+    }
+
+    /// Records a failure unless its path already has one
+    fn push_error(&mut self, error: EntryError) {
+        if self.error_index.contains_key(&error.path) {
+            return;
+        }
+        self.error_index.insert(error.path.clone(), self.errors.len());
+        self.errors.push(error);
+    }
+
+    /// With --ignore-errors, records an entry whose type or metadata could
+    /// not be read, once per entry. A dangling symlink has no target to read,
+    /// which is not an error.
+    fn record_entry_error(&mut self, path: &Path, err: &std::io::Error) {
+        if !self.config.ignore_errors {
+            return;
+        }
+        if err.kind() == std::io::ErrorKind::NotFound && fs::symlink_metadata(path).is_ok() {
+            return;
+        }
+        self.push_error(EntryError {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+            marker: "error reading entry",
+        });
+    }
+
+    /// The metadata of an entry, following symlinks; with `report`,
+    /// failures are recorded by `record_entry_error`
+    fn entry_metadata(&mut self, path: &Path, report: bool) -> Option<fs::Metadata> {
+        fs::metadata(path)
+            .inspect_err(|err: &std::io::Error| {
+                if report {
+                    self.record_entry_error(path, err);
+                }
+            })
+            .ok()
+    }
+
+    /// Reads, sorts and filters the entries of a directory listed at `level`
+    fn read_entries(
+        &mut self,
+        dir: &Path,
+        base_dir: &Path,
+        level: usize,
+        stats: &mut FileStats,
+    ) -> TreeResult<Vec<fs::DirEntry>> {
+        // Check max depth
+        if self.exceeds_max_depth(level) {
+            return Ok(Vec::new());
+        }
+
+        self.debug(format_args!("enter {}", dir.display()));
+        let started: Option<Instant> = self.config.debug.then(Instant::now);
+        if self.config.gitignore {
+            self.gitignore.enter_dir(dir);
+        }
+        let entries: fs::ReadDir = fs::read_dir(dir)?;
+        let mut readable: Vec<fs::DirEntry> = Vec::new();
+        for entry in entries {
+            match entry {
+                Ok(entry) => {
+                    // The type usually comes with the listing; reading it on
+                    // its own can fail, and the entry is then listed as a file
+                    if let Err(err) = entry.file_type() {
+                        self.record_entry_error(&entry.path(), &err);
+                    }
+                    readable.push(entry);
+                }
+                Err(err) if self.config.ignore_errors => self.record_error(dir, &err),
+                Err(_) => {}
+            }
+        }
+        let mut entries: Vec<fs::DirEntry> = readable;
+
+        // Sort entries by name; folding allocates a key per entry, so byte
+        // order stays the default
+        if self.config.locale_sort {
+            entries.sort_by_cached_key(|entry: &fs::DirEntry| collate::sort_key(&entry.file_name().to_string_lossy()));
+        } else {
+            entries.sort_by_key(|entry: &fs::DirEntry| entry.file_name());
+        }
+
+        // Stable sorts keep the name order for entries the --sort keys tie on
+        if !self.config.sort.is_empty() {
+            let mut keyed: Vec<(SortValues, fs::DirEntry)> = entries
+                .into_iter()
+                .map(|entry: fs::DirEntry| {
+                    (self.sort_values(entry.file_name().to_string_lossy().to_string(), &entry.path()), entry)
+                })
+                .collect();
+            keyed.sort_by(|(a, _), (b, _)| self.compare_sort_values(a, b));
+            entries = keyed.into_iter().map(|(_, entry)| entry).collect();
+        }
+
+        // Stable sort keeps the name order within the directory and file groups
+        if self.config.dirsfirst {
+            entries.sort_by_key(|entry: &fs::DirEntry| !entry_is_dir(entry));
+        }
+
+        // Filter out entries based on config
+        let read: usize = entries.len();
+        entries.retain(|entry: &fs::DirEntry| self.should_include_entry(entry, base_dir, stats));
+
+        // Fisher-Yates shuffle; --dirsfirst still groups directories first
+        if self.config.shuffle.is_some() {
+            for index in (1..entries.len()).rev() {
+                let other: usize = (next_random(&mut self.shuffle_state) % (index as u64 + 1)) as usize;
+                entries.swap(index, other);
+            }
+            if self.config.dirsfirst {
+                entries.sort_by_key(|entry: &fs::DirEntry| !entry_is_dir(entry));
+            }
+        }
+
+        if let Some(started) = started {
+            self.debug(format_args!(
+                "leave {}: {} of {} entries kept in {:.3} ms",
+                dir.display(),
+                entries.len(),
+                read,
+                started.elapsed().as_secs_f64() * 1000.0
+            ));
+        }
+
+        Ok(entries)
+    }
+
+    /// Collects what the --sort keys compare; metadata is only read when a
+    /// time or size key needs it
+    fn sort_values(&self, file_name: String, path: &Path) -> SortValues {
+        let needs_metadata: bool = self
+            .config
+            .sort
+            .iter()
+            .any(|key: &SortKey| matches!(key.field, SortField::Time | SortField::Size));
+        let metadata: Option<fs::Metadata> = needs_metadata.then(|| fs::metadata(path).ok()).flatten();
+
+        SortValues {
+            name: if self.config.locale_sort { collate::sort_key(&file_name).0 } else { file_name },
+            extension: path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+            modified: metadata.as_ref().and_then(|metadata: &fs::Metadata| metadata.modified().ok()),
+            size: metadata.as_ref().map_or(0, fs::Metadata::len),
+        }
+    }
+
+    /// Puts nodes merged from two trees in the order `read_entries` lists a
+    /// directory in: by name, then by the --sort keys and with --dirsfirst
+    /// directories first. Shuffled listings keep the name order.
+    fn sort_nodes(&self, nodes: &mut Vec<TreeNode>) {
+        if self.config.locale_sort {
+            nodes.sort_by_cached_key(|node: &TreeNode| collate::sort_key(&node.name));
+        } else {
+            nodes.sort_by(|a: &TreeNode, b: &TreeNode| a.name.cmp(&b.name));
+        }
+
+        if !self.config.sort.is_empty() {
+            let mut keyed: Vec<(SortValues, TreeNode)> = std::mem::take(nodes)
+                .into_iter()
+                .map(|node: TreeNode| (self.sort_values(node.name.clone(), &node.path), node))
+                .collect();
+            keyed.sort_by(|(a, _), (b, _)| self.compare_sort_values(a, b));
+            *nodes = keyed.into_iter().map(|(_, node)| node).collect();
+        }
+
+        if self.config.dirsfirst {
+            nodes.sort_by_key(|node: &TreeNode| !node.is_dir);
+        }
+    }
+
+    fn compare_sort_values(&self, a: &SortValues, b: &SortValues) -> std::cmp::Ordering {
+        self.config
+            .sort
+            .iter()
+            .map(|key: &SortKey| {
+                let ordering: std::cmp::Ordering = match key.field {
+                    SortField::Name => a.name.cmp(&b.name),
+                    SortField::Time => a.modified.cmp(&b.modified),
+                    SortField::Size => a.size.cmp(&b.size),
+                    SortField::Extension => a.extension.cmp(&b.extension),
+                };
+                if key.descending { ordering.reverse() } else { ordering }
+            })
+            .find(|ordering: &std::cmp::Ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    }
+
+    /// Counts an entry shown at `depth`, where the root's entries are at 1
+    fn count_entry(&mut self, node: &TreeNode, depth: usize, base_dir: &Path, stats: &mut FileStats) {
+        if !self.counts_in_report(&node.path, base_dir) {
+            return;
+        }
+
+        // Followed symlinks count as their targets unless asked otherwise
+        if node.is_symlink && self.config.no_dereference_report {
+            stats.links += 1;
+        } else if node.is_dir {
+            stats.dirs += 1;
+            if self.config.per_level_stats {
+                stats.level_mut(depth).0 += 1;
+            }
+            // Directories only take up space of their own under --du
+            if self.config.du && !self.config.no_metadata {
+                stats.bytes += self.entry_size_or_record(&node.path);
+            }
+        } else {
+            stats.files += 1;
+            if self.config.per_level_stats {
+                stats.level_mut(depth).1 += 1;
+            }
+            let by_ext: bool = (self.config.size_by_ext || self.config.stats_file.is_some()) && !self.config.no_metadata;
+            if self.tracks_bytes() || by_ext {
+                let bytes: u64 = self.entry_size_or_record(&node.path);
+                if self.tracks_bytes() {
+                    stats.bytes += bytes;
+                }
+                if by_ext {
+                    let ext: String = node
+                        .path
+                        .extension()
+                        .map(|ext| ext.to_string_lossy().to_lowercase())
+                        .unwrap_or_default();
+                    *stats.ext_bytes.entry(ext).or_default() += bytes;
+                }
+            }
+        }
+    }
+
+    fn tracks_bytes(&self) -> bool {
+        // Sizes cost a metadata read per file, so only collect them when shown
+        (self.config.report_format == ReportFormat::Json || self.config.du || self.config.stats_file.is_some())
+            && !self.config.no_metadata
+    }
+
+    /// A byte count in readable units: powers of 1000 with --si, else 1024
+    fn human_size(&self, bytes: u64) -> String {
+        if self.config.si {
+            size::format_si_size(bytes)
+        } else {
+            size::format_human_size(bytes)
+        }
+    }
+
+    fn shows_sizes(&self) -> bool {
+        self.config.size || self.config.du
+    }
+
+    /// Bytes an entry accounts for: its length, or under --du the disk
+    /// blocks it occupies unless --apparent-size is given
+    fn entry_size(&self, path: &Path) -> std::io::Result<u64> {
+        // Directory symlinks are always followed; file symlinks unless
+        // --no-dereference-files is given
+        let metadata: fs::Metadata = if !self.config.no_dereference_files || path.is_dir() {
+            fs::metadata(path)?
+        } else {
+            fs::symlink_metadata(path)?
+        };
+        Ok(self.metadata_size(&metadata))
+    }
+
+    /// `entry_size`, counting an unreadable entry as empty and recording why
+    fn entry_size_or_record(&mut self, path: &Path) -> u64 {
+        self.entry_size(path).unwrap_or_else(|err: std::io::Error| {
+            self.record_entry_error(path, &err);
+            0
+        })
+    }
+
+    fn metadata_size(&self, metadata: &fs::Metadata) -> u64 {
+        #[cfg(unix)]
+        if self.config.du && !self.config.apparent_size {
+            use std::os::unix::fs::MetadataExt;
+            return metadata.blocks() * 512;
+        }
+
+        metadata.len()
+    }
+
+    /// The size of everything below `dir`, unfiltered and without following
+    /// symlinks: file lengths, or disk usage of files and directories with
+    /// --du. One probe records the totals
+    /// of all subdirectories too, so nested directories are not re-walked.
+    fn dir_total(&mut self, dir: &Path) -> u64 {
+        if let Some(&total) = self.dir_totals.get(dir) {
+            return total;
+        }
+
+        // Directories in walk order with their parent's index and the bytes
+        // found so far; a parent always comes before its children
+        let mut dirs: Vec<(PathBuf, Option<usize>, u64)> = vec![(dir.to_path_buf(), None, 0)];
+        let mut index: usize = 0;
+        while index < dirs.len() {
+            if let Ok(entries) = fs::read_dir(&dirs[index].0) {
+                for entry in entries.flatten() {
+                    let metadata: fs::Metadata = match entry.metadata() {
+                        Ok(metadata) => metadata,
+                        Err(err) => {
+                            self.record_entry_error(&entry.path(), &err);
+                            continue;
+                        }
+                    };
+                    let bytes: u64 = self.metadata_size(&metadata);
+                    if metadata.is_dir() {
+                        // Like the --du rollup, only disk usage counts directories
+                        let own: u64 = if self.config.du { bytes } else { 0 };
+                        dirs.push((entry.path(), Some(index), own));
+                    } else {
+                        dirs[index].2 += bytes;
+                    }
+                }
+            }
+            index += 1;
+        }
+
+        for index in (1..dirs.len()).rev() {
+            if let Some(parent) = dirs[index].1 {
+                dirs[parent].2 += dirs[index].2;
+            }
+        }
+        let total: u64 = dirs[0].2;
+        self.dir_totals.extend(dirs.into_iter().map(|(path, _, bytes)| (path, bytes)));
+        total
+    }
+
+    fn counts_in_report(&self, path: &Path, base_dir: &Path) -> bool {
+        if !self.config.report_visible_only {
+            return true;
+        }
+
+        // Anything that would be hidden without -a, including entries inside
+        // hidden directories, is left out of the counts
+        let relative_path: &Path = path.strip_prefix(base_dir).unwrap_or(path);
+        !relative_path
+            .components()
+            .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
+    fn exceeds_max_depth(&self, level: usize) -> bool {
+        self.max_depth.is_some_and(|max_depth: usize| level > max_depth)
+    }
+
+    /// Whether any non-directory below `dir` passes the filters, looking
+    /// breadth-first through the directories that pass them too. Nothing is
+    /// logged, explained or recorded for the entries looked at, and the
+    /// search gives up after `probe_limit` entries, keeping the directory,
+    /// so it cannot undo what -L saves on huge trees.
+    fn has_visible_files(&mut self, dir: &Path, base_dir: &Path) -> bool {
+        let mut scratch_stats: FileStats = FileStats::new();
+        let mut visited: HashSet<PathBuf> = HashSet::new();
+        let mut pending: VecDeque<PathBuf> = VecDeque::from([dir.to_path_buf()]);
+        let mut budget: usize = self.probe_limit();
+        while let Some(dir) = pending.pop_front() {
+            // Symlinked directories may lead back to one already searched
+            if !dir.canonicalize().is_ok_and(|canonical: PathBuf| visited.insert(canonical)) {
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.filter_map(Result::ok) {
+                if budget == 0 {
+                    return true;
+                }
+                budget -= 1;
+                if self.exclusion_reason(&entry, base_dir, &mut scratch_stats, false).is_some() {
+                    continue;
+                }
+                if !entry_is_dir(&entry) {
+                    return true;
+                }
+                pending.push_back(entry.path());
+            }
+        }
+        false
+    }
+
+    /// Entries `has_visible_files` may look at below one directory: the
+    /// --max-entries-total budget if smaller than `PROBE_LIMIT`
+    fn probe_limit(&self) -> usize {
+        self.config.max_entries_total.map_or(PROBE_LIMIT, |limit: usize| limit.min(PROBE_LIMIT))
+    }
+
+    /// Prints `nodes` below `prefix`, followed by markers for the `omitted`
+    /// entries
+    fn render_children(
+        &self,
+        nodes: &[TreeNode],
+        omitted: Omitted,
+        prefix: &str,
+        level: usize,
+        output: &mut String,
+    ) {
+        // Iterative like the traversal: a frame per directory being printed
+        let mut stack: Vec<RenderFrame> = vec![RenderFrame {
+            nodes,
+            names: self.aligned_names(nodes),
+            index: 0,
+            prefix: prefix.to_string(),
+            level,
+            omitted,
+        }];
+
+        while let Some(frame) = stack.last_mut() {
+            let index: usize = frame.index;
+            let Some(node) = frame.nodes.get(index) else {
+                let Omitted { entries, files } = frame.omitted;
+                if files > 0 {
+                    let (connector, _) = self.painted_connectors(entries == 0, frame.level);
+                    output.push_str(&format!("{}{}… ({} more files)\n", frame.prefix, connector, files));
+                }
+                if entries > 0 {
+                    let (connector, _) = self.painted_connectors(true, frame.level);
+                    output.push_str(&format!("{}{}… and {} more\n", frame.prefix, connector, entries));
+                }
+                stack.pop();
+                continue;
+            };
+            frame.index += 1;
+
+            // With --columns or --compact-files, a run of files is printed at once
+            let run_len: usize = if self.config.columns || self.config.compact_files {
+                self.file_run_len(&frame.nodes[index..])
+            } else {
+                0
+            };
+            if run_len > 1 {
+                frame.index = index + run_len;
+            }
+
+            let nodes: &[TreeNode] = frame.nodes;
+            let level: usize = frame.level;
+            let prefix: &str = &frame.prefix;
+            let omitted: Omitted = frame.omitted;
+            let is_last: bool = index == nodes.len() - 1 && omitted.is_empty();
+
+            // Separate the directory group from the file group
+            if self.config.dirsfirst
+                && self.config.group_separator
+                && !node.is_dir
+                && index > 0
+                && nodes[index - 1].is_dir
+            {
+                if self.config.no_indent || self.config.use_tabs {
+                    output.push_str(&format!("{}\n", prefix));
+                } else if self.use_color && self.config.depth_colors {
+                    let code: &str = color::depth_color(level);
+                    output.push_str(&format!("{}{}\n", prefix, color::paint(self.vertical_line(), code)));
+                } else {
+                    output.push_str(&format!("{}{}\n", prefix, self.vertical_line()));
+                }
+            }
+
+            if run_len > 1 {
+                let files: &[TreeNode] = &nodes[index..index + run_len];
+                let ends_listing: bool = index + run_len == nodes.len() && omitted.is_empty();
+                if self.config.compact_files {
+                    self.render_file_list(files, prefix, level, ends_listing, output);
+                } else {
+                    self.render_file_columns(files, prefix, level, ends_listing, output);
+                }
+                continue;
+            }
+
+            // Calculate new prefix for child items
+            let (connector, new_prefix) = self.painted_connectors(is_last, level);
+
+            // Add current entry to output
+            let display_name: String = match frame.names.get(index) {
+                Some(name) => name.clone(),
+                None => self.format_display_name(node),
+            };
+            output.push_str(&format!("{}{}{}\n", prefix, connector, display_name));
+
+            // Comments line up with the entry's name
+            if let Some(comment) = self.info_comments.get(&node.path) {
+                let indent: String = format!("{}{}", prefix, new_prefix);
+                let width: usize = self.width.saturating_sub(color::visible_width(&indent));
+                for line in info::comment_block(comment, self.charset, width) {
+                    output.push_str(&format!("{}{}\n", indent, line));
+                }
+            }
+            for line in self.exec_output_lines(node) {
+                output.push_str(&format!("{}{}{}\n", prefix, new_prefix, line));
+            }
+
+            if node.is_dir {
+                let child_prefix: String = format!("{}{}", prefix, new_prefix);
+                stack.push(RenderFrame {
+                    nodes: &node.children,
+                    names: self.aligned_names(&node.children),
+                    index: 0,
+                    prefix: child_prefix,
+                    level: level + 1,
+                    omitted: self.omitted_count(&node.path),
+                });
+            }
+        }
+    }
+
+    fn omitted_count(&self, path: &Path) -> Omitted {
+        self.omitted.get(path).copied().unwrap_or_default()
+    }
+
+    /// Counts the files at the start of `nodes` that --columns lays out
+    /// together; entries with info comments keep their own line
+    fn file_run_len(&self, nodes: &[TreeNode]) -> usize {
+        nodes
+            .iter()
+            .take_while(|node: &&TreeNode| {
+                !node.is_dir
+                    && !self.info_comments.contains_key(&node.path)
+                    && self.exec_output_lines(node).next().is_none()
+            })
+            .count()
+    }
+
+    /// The --exec output shown beneath a file with --exec-output
+    fn exec_output_lines<'n>(&'n self, node: &TreeNode) -> impl Iterator<Item = &'n str> {
+        self.exec_results
+            .get(&node.path)
+            .filter(|_| self.config.exec_output)
+            .into_iter()
+            .flat_map(|result: &ExecResult| result.output.lines())
+    }
+
+    /// Prints sibling files in as many columns as fit the terminal, filled
+    /// top to bottom like `ls`. Each row gets a connector of its own.
+    fn render_file_columns(
+        &self,
+        files: &[TreeNode],
+        prefix: &str,
+        level: usize,
+        ends_listing: bool,
+        output: &mut String,
+    ) {
+        let names: Vec<String> = files.iter().map(|node: &TreeNode| self.format_display_name(node)).collect();
+        let widest: usize = names.iter().map(|name: &String| color::visible_width(name)).max().unwrap_or(0);
+        let column_width: usize = widest + COLUMN_GAP;
+
+        let used: usize = color::visible_width(prefix) + self.connectors(false).0.chars().count();
+        let available: usize = self.width.saturating_sub(used) + COLUMN_GAP;
+        let columns: usize = (available / column_width).clamp(1, names.len());
+        let rows: usize = names.len().div_ceil(columns);
+
+        for row in 0..rows {
+            let (connector, _) = self.painted_connectors(ends_listing && row == rows - 1, level);
+            let mut line: String = String::new();
+
+            for column in 0..columns {
+                let Some(name) = names.get(column * rows + row) else {
+                    break;
+                };
+                line.push_str(name);
+                if names.get((column + 1) * rows + row).is_some() {
+                    line.push_str(&" ".repeat(column_width - color::visible_width(name)));
+                }
+            }
+
+            output.push_str(&format!("{}{}{}\n", prefix, connector, line));
+        }
+    }
+
+    /// Prints sibling files as one comma-separated entry, wrapped to the
+    /// terminal width with continuation lines indented like children
+    fn render_file_list(
+        &self,
+        files: &[TreeNode],
+        prefix: &str,
+        level: usize,
+        ends_listing: bool,
+        output: &mut String,
+    ) {
+        let (connector, new_prefix) = self.painted_connectors(ends_listing, level);
+        let used: usize = color::visible_width(prefix) + color::visible_width(&connector);
+        let available: usize = self.width.saturating_sub(used).max(1);
+
+        let mut lines: Vec<String> = vec![String::new()];
+        for (index, node) in files.iter().enumerate() {
+            let mut name: String = self.format_display_name(node);
+            if index + 1 < files.len() {
+                name.push(',');
+            }
+
+            let line: &mut String = lines.last_mut().expect("lines is never empty");
+            if line.is_empty() {
+                line.push_str(&name);
+            } else if color::visible_width(line) + 1 + color::visible_width(&name) <= available {
+                line.push(' ');
+                line.push_str(&name);
+            } else {
+                lines.push(name);
+            }
+        }
+
+        for (index, line) in lines.iter().enumerate() {
+            let lead: &str = if index == 0 { &connector } else { &new_prefix };
+            output.push_str(&format!("{}{}{}\n", prefix, lead, line));
+        }
+    }
+
+    /// Connectors for an entry at `level`, colored by depth when enabled
+    fn painted_connectors(&self, is_last: bool, level: usize) -> (String, String) {
+        let (connector, new_prefix) = self.connectors(is_last);
+        if self.use_color && self.config.depth_colors {
+            let code: &str = color::depth_color(level);
+            (color::paint(connector, code), color::paint(new_prefix, code))
+        } else {
+            (connector.to_string(), new_prefix.to_string())
+        }
+    }
+
+    fn connectors(&self, is_last: bool) -> (&'static str, &'static str) {
+        // A tab-indented outline: one tab per level and no connectors
+        if self.config.use_tabs {
+            return ("\t", "\t");
+        }
+
+        match (self.config.no_indent, self.charset, is_last) {
+            (true, _, _) => ("", ""),
+            (false, Charset::Utf8 | Charset::Auto, true) => ("└── ", "    "),
+            (false, Charset::Utf8 | Charset::Auto, false) => ("├── ", "│   "),
+            (false, Charset::Ascii, true) => ("`-- ", "    "),
+            (false, Charset::Ascii, false) => ("|-- ", "|   "),
+        }
+    }
+
+    fn vertical_line(&self) -> &'static str {
+        match self.charset {
+            Charset::Utf8 | Charset::Auto => "│",
+            Charset::Ascii => "|",
+        }
+    }
+
+    fn dir_suffix(&self) -> &'static str {
+        if self.config.no_trailing_slash { "" } else { "/" }
+    }
+
+    fn should_include_entry(
+        &mut self,
+        entry: &fs::DirEntry,
+        base_dir: &Path,
+        stats: &mut FileStats,
+    ) -> bool {
+        let reason: Option<&str> = self.exclusion_reason(entry, base_dir, stats, true);
+        match reason {
+            Some(reason) => self.debug(format_args!("skip {} ({})", entry.path().display(), reason)),
+            None => self.debug(format_args!("keep {}", entry.path().display())),
+        }
+        reason.is_none()
+    }
+
+    /// Returns why an entry is left out of the listing, or `None` to list it.
+    /// Without `report` nothing is explained or recorded as an error, for
+    /// entries that are only looked at and never listed.
+    fn exclusion_reason(
+        &mut self,
+        entry: &fs::DirEntry,
+        base_dir: &Path,
+        stats: &mut FileStats,
+        report: bool,
+    ) -> Option<&'static str> {
+        let path: std::path::PathBuf = entry.path();
+        let file_name: std::ffi::OsString = entry.file_name();
+        let is_dir: bool = entry_is_dir(entry);
+
+        // Never list the `.` and `..` pseudo-entries, even with -a
+        if file_name == "." || file_name == ".." {
+            return Some("pseudo-entry");
+        }
+
+        // Skip hidden files unless -a flag is provided; --ignore-hidden-dirs
+        // lets dotfiles through and still skips hidden directories
+        if !self.config.all
+            && file_name.to_string_lossy().starts_with('.')
+            && (is_dir || !self.config.ignore_hidden_dirs)
+        {
+            stats.hidden += 1;
+            return Some("hidden");
+        }
+
+        // Skip entries matching -I or --exclude-from patterns, or ignored by
+        // .gitignore; --ignore-priority decides which source wins
+        if self.is_ignored(&path, &file_name.to_string_lossy(), is_dir, base_dir, stats, report) {
+            return Some("ignored");
+        }
+
+        // Only list files matching -P patterns; directories are always traversed
+        if !is_dir
+            && !self.listing_patterns.is_empty()
+            && !self.listing_patterns.matches(&file_name.to_string_lossy())
+        {
+            return Some("no -P match");
+        }
+
+        // Filter files by extension; directories are always traversed
+        if !is_dir && (!self.config.include_ext.is_empty() || !self.config.exclude_ext.is_empty()) {
+            let extension: String = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            if !self.config.include_ext.is_empty() && !self.config.include_ext.contains(&extension) {
+                return Some("extension not included");
+            }
+            if self.config.exclude_ext.contains(&extension) {
+                return Some("extension excluded");
+            }
+        }
+
+        // With type filters, only files of the selected special kinds are
+        // listed; there are none to tell apart off Unix, so the filters
+        // leave everything listed there
+        if cfg!(unix) && !is_dir {
+            let kind: Option<SpecialKind> = entry.file_type().ok().and_then(SpecialKind::of);
+            if self.config.fifos || self.config.sockets || self.config.devices {
+                let wanted: bool = match kind {
+                    Some(SpecialKind::Fifo) => self.config.fifos,
+                    Some(SpecialKind::Socket) => self.config.sockets,
+                    Some(SpecialKind::Device) => self.config.devices,
+                    None => false,
+                };
+                if !wanted {
+                    return Some("file type");
+                }
+            }
+            let excluded: bool = match kind {
+                Some(SpecialKind::Fifo) => self.config.exclude_fifos,
+                Some(SpecialKind::Socket) => self.config.exclude_sockets,
+                Some(SpecialKind::Device) => self.config.exclude_devices,
+                None => false,
+            };
+            if excluded {
+                return Some("file type excluded");
+            }
+        }
+
+        // Symlink filters look at the link itself, never at its target; with
+        // --only-symlinks real directories are still traversed
+        if self.config.exclude_symlinks || self.config.only_symlinks {
+            let is_symlink: bool = path
+                .symlink_metadata()
+                .is_ok_and(|metadata: fs::Metadata| metadata.file_type().is_symlink());
+            if self.config.exclude_symlinks && is_symlink {
+                return Some("symlink");
+            }
+            if self.config.only_symlinks && !is_symlink && !is_dir {
+                return Some("not a symlink");
+            }
+        }
+
+        // Filter files by modification date; directories are always traversed
+        if !is_dir && (self.config.since.is_some() || self.config.until.is_some()) {
+            let Some(modified) = self.entry_metadata(&path, report).and_then(|m: fs::Metadata| m.modified().ok()) else {
+                return Some("no modification time");
+            };
+            if self.config.since.is_some_and(|since: DateBound| !since.is_on_or_after(modified))
+                || self.config.until.is_some_and(|until: DateBound| !until.is_on_or_before(modified))
+            {
+                return Some("outside date range");
+            }
+        }
+
+        // Evaluate --filter, reading metadata only when a condition needs it
+        if let Some(filter) = &self.config.filter {
+            let name: std::borrow::Cow<str> = file_name.to_string_lossy();
+            let ext: String = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let metadata: Option<fs::Metadata> = if filter.needs_metadata() { self.entry_metadata(&path, report) } else { None };
+            let attributes: EntryAttributes = EntryAttributes {
+                name: &name,
+                ext: &ext,
+                size: metadata.as_ref().map_or(0, fs::Metadata::len),
+                modified: metadata.as_ref().and_then(|m: &fs::Metadata| m.modified().ok()),
+                is_dir,
+                hidden: name.starts_with('.'),
+            };
+            if !filter.matches(&attributes) {
+                return Some("no --filter match");
+            }
+        }
+
+        // Skip files if -d flag is provided
+        if self.config.dirs_only && !is_dir {
+            return Some("not a directory");
+        }
+
+        // Skip .git directory if gitignore option is used; --hide-git skips
+        // it anywhere, along with the `.git` files of submodules
+        if (self.config.gitignore && path == base_dir.join(".git")) || (self.config.hide_git && file_name == ".git") {
+            return Some(".git directory");
+        }
+
+        None
+    }
+
+    /// Logs a --debug message to stderr; the message is only formatted when
+    /// the flag is set
+    fn debug(&self, message: std::fmt::Arguments) {
+        if self.config.debug {
+            eprintln!("[debug] {}", message);
+        }
+    }
+
+    fn is_ignored(
+        &self,
+        path: &Path,
+        name: &str,
+        is_dir: bool,
+        base_dir: &Path,
+        stats: &mut FileStats,
+        report: bool,
+    ) -> bool {
+        let excluded: bool = self.ignore_patterns.matches(name);
+        if !self.config.gitignore {
+            return excluded;
+        }
+
+        let decision: Option<IgnoreMatch> = self.gitignore.decision(path, base_dir, is_dir);
+        let ignored_by_gitignore: bool = decision.is_some_and(|decision: IgnoreMatch| decision.ignored);
+
+        // Inverted for --show-ignored-only: excludes still apply, and
+        // directories are kept to be searched for ignored entries
+        if self.config.show_ignored_only {
+            return excluded || !(ignored_by_gitignore || is_dir);
+        }
+
+        let (ignored, by_gitignore): (bool, bool) = match (self.config.ignore_priority, decision) {
+            // Excludes are applied last, so a gitignore negation cannot
+            // bring back an excluded entry
+            (IgnorePriority::Excludes, _) if excluded => (true, false),
+            (IgnorePriority::Excludes, _) => (ignored_by_gitignore, ignored_by_gitignore),
+            // Whatever .gitignore says wins; excludes only cover the rest
+            (IgnorePriority::Gitignore, Some(decision)) => (decision.ignored, decision.ignored),
+            (IgnorePriority::Gitignore, None) => (excluded, false),
+        };
+
+        if by_gitignore {
+            stats.ignored += 1;
+        }
+
+        if self.config.explain_ignore
+            && report
+            && by_gitignore
+            && let Some(decision) = decision
+        {
+            eprintln!(
+                "ignored {} by pattern {} from {}",
+                path.display(),
+                decision.pattern,
+                decision.source.display()
+            );
+        }
+
+        ignored
+    }
+
+    fn format_display_name(&self, node: &TreeNode) -> String {
+        match self.display_parts(node) {
+            (Some(metadata), name) => format!("{}  {}", metadata, name),
+            (None, name) => name,
+        }
+    }
+
+    /// The display names of sibling entries for --align-names, with their
+    /// metadata blocks padded to the widest one so the names line up
+    fn aligned_names(&self, nodes: &[TreeNode]) -> Vec<String> {
+        if !self.config.align_names {
+            return Vec::new();
+        }
+
+        let parts: Vec<(Option<String>, String)> = nodes.iter().map(|node: &TreeNode| self.display_parts(node)).collect();
+        let width: usize = parts
+            .iter()
+            .filter_map(|(metadata, _)| metadata.as_deref())
+            .map(color::visible_width)
+            .max()
+            .unwrap_or(0);
+        parts
+            .into_iter()
+            .map(|(metadata, name)| match metadata {
+                Some(metadata) => {
+                    let padding: String = " ".repeat(width - color::visible_width(&metadata));
+                    format!("{}{}  {}", metadata, padding, name)
+                }
+                // Entries without metadata, like directories under --lines
+                None if width > 0 => format!("{}  {}", " ".repeat(width), name),
+                None => name,
+            })
+            .collect()
+    }
+
+    /// The bracketed metadata columns of an entry, if any, and its name with
+    /// markers and annotations
+    fn display_parts(&self, node: &TreeNode) -> (Option<String>, String) {
+        let name: String = if self.config.base64_names {
+            let encoded: String = format!("{}{}", BASE64_MARKER, encoding::base64(&path_bytes(&self.raw_name(node))));
+            if node.is_dir && !self.config.full_path {
+                format!("{}{}", encoded, self.dir_suffix())
+            } else {
+                encoded
+            }
+        } else if self.config.full_path {
+            self.with_separator(self.replace_home(&self.display_path(&node.path)))
+        } else if node.is_dir {
+            format!("{}{}", node.name, self.dir_suffix())
+        } else {
+            node.name.clone()
+        };
+
+        let name: String = match self.entry_error(&node.path) {
+            Some(error) => format!("{} [{}]", name, error.marker),
+            None if self.loops.contains(&node.path) => format!("{} [recursive]", name),
+            None if self.unexpanded.contains(&node.path) => format!("{} [...]", name),
+            None if self.link_limited.contains(&node.path) => format!("{} [symlink depth]", name),
+            None if self.too_large.contains(&node.path) => {
+                let total: u64 = self.dir_totals.get(&node.path).copied().unwrap_or(0);
+                format!("{} [too large, {}]", name, self.human_size(total))
+            }
+            None if self.empty_dirs.contains(&node.path) => format!("{} (empty)", name),
+            None => name,
+        };
+
+        let name: String = match node.special {
+            Some(kind) => format!("{}{}", name, kind.marker()),
+            None => name,
+        };
+
+        // A diff marker takes over the name color from the git status
+        let name: String = match (node.diff, self.git_status(node)) {
+            (Some(mark), _) if self.use_color => color::paint(&format!("{} {}", mark.marker(), name), mark.color()),
+            (Some(mark), _) => format!("{} {}", mark.marker(), name),
+            (None, Some(status)) => color::paint(&name, status.color()),
+            (None, None) if self.is_stale(node) => color::paint(&name, STALE_COLOR),
+            (None, None) => name,
+        };
+
+        // Annotations follow the name
+        let mut name: String = name;
+        if self.config.encoding_detect && node.path.is_file() && encoding::is_non_utf8(&node.path) {
+            name.push_str(" [non-utf8]");
+        }
+        if self.is_binary(node) {
+            name.push_str(" [bin]");
+        }
+        if let Some(count) = self.duplicates.get(&node.path) {
+            name.push_str(&format!(" [dup {}]", count));
+        }
+        if self.is_recent(node) {
+            name.push_str(" [recent]");
+        }
+        if self.mounts.contains(&node.path) {
+            name.push_str(" [mount]");
+        }
+        match self.exec_results.get(&node.path) {
+            Some(result) if result.succeeded() => {}
+            Some(ExecResult { code: Some(code), .. }) => name.push_str(&format!(" [exit {}]", code)),
+            Some(_) => name.push_str(" [exec failed]"),
+            None => {}
+        }
+
+        // Metadata columns share a single bracket before the name
+        let mut metadata: Vec<String> = Vec::new();
+        if let Some(digest) = self.file_checksum(node) {
+            metadata.push(digest);
+        }
+        if let Some(size) = node.size.filter(|_| self.config.si) {
+            metadata.push(format!("{:>11}", self.human_size(size)));
+        } else if let Some(size) = node.size {
+            metadata.push(format!("{:>11}", size));
+        } else if self.shows_sizes() && self.config.no_metadata {
+            metadata.push(format!("{:>11}", "-"));
+        }
+        if let Some(lines) = self.line_count(node) {
+            metadata.push(lines);
+        }
+        if let Some(links) = self.link_count(node) {
+            metadata.push(links);
+        }
+        if let Some(date) = self.format_date(node) {
+            metadata.push(date);
+        }
+
+        if metadata.is_empty() {
+            (None, name)
+        } else {
+            (Some(format!("[{}]", metadata.join(" "))), name)
+        }
+    }
+
+    fn entry_error(&self, path: &Path) -> Option<&EntryError> {
+        self.error_index.get(path).map(|&index: &usize| &self.errors[index])
+    }
+
+    /// Whether a file was last modified longer ago than --stale-after
+    fn is_stale(&self, node: &TreeNode) -> bool {
+        let Some(threshold) = self.config.stale_after else {
+            return false;
+        };
+        if !self.use_color || node.is_dir || self.config.no_metadata {
+            return false;
+        }
+
+        fs::symlink_metadata(&node.path)
+            .and_then(|metadata: fs::Metadata| metadata.modified())
+            .ok()
+            .and_then(|modified: SystemTime| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age: Duration| age > threshold)
+    }
+
+    /// Whether a file was modified after the --recent threshold
+    fn is_recent(&self, node: &TreeNode) -> bool {
+        let Some(since) = self.recent_since else {
+            return false;
+        };
+
+        !node.is_dir
+            && !self.config.no_metadata
+            && fs::metadata(&node.path)
+                .and_then(|metadata: fs::Metadata| metadata.modified())
+                .is_ok_and(|modified: SystemTime| modified > since)
+    }
+
+    fn git_status(&self, node: &TreeNode) -> Option<GitStatus> {
+        if self.git_statuses.is_empty() || node.is_dir {
+            return None;
+        }
+
+        let path: PathBuf = node.path.canonicalize().ok()?;
+        self.git_statuses.get(&path).copied()
+    }
+
+    fn format_date(&self, node: &TreeNode) -> Option<String> {
+        if !self.config.show_date {
+            return None;
+        }
+        if self.config.no_metadata {
+            return Some(String::from("-"));
+        }
+
+        let modified: SystemTime = match fs::metadata(&node.path).and_then(|m: fs::Metadata| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return Some(String::from("?")),
+        };
+
+        // --timefmt wins over --time-style when both are given
+        let format: &str = match (&self.config.timefmt, self.config.time_style) {
+            (Some(timefmt), _) => timefmt,
+            (None, Some(TimeStyle::Relative)) => {
+                return Some(time::format_relative(modified, SystemTime::now()));
+            }
+            (None, Some(TimeStyle::Iso)) => "%Y-%m-%d",
+            (None, Some(TimeStyle::LongIso)) => "%Y-%m-%d %H:%M",
+            (None, Some(TimeStyle::FullIso)) => "%Y-%m-%d %H:%M:%S +0000",
+            (None, None) => "%b %e %H:%M",
+        };
+
+        Some(time::format_time(modified, format))
+    }
+
+    fn file_checksum(&self, node: &TreeNode) -> Option<String> {
+        // Only regular files are hashed; directories keep a bare name
+        let algorithm = self.config.checksum?;
+        if !node.path.is_file() {
+            return None;
+        }
+
+        let digest: String = checksum::file_digest(&node.path, algorithm)
+            .unwrap_or_else(|_| String::from("unreadable"));
+        Some(digest)
+    }
+
+    /// The `N lines` (or `N+ lines` past --max-line-count) shown by --lines
+    fn line_count(&self, node: &TreeNode) -> Option<String> {
+        if !self.config.lines || !node.path.is_file() {
+            return None;
+        }
+
+        // Newlines in binary data say nothing about its length
+        if self.is_binary(node) {
+            return Some(String::from("binary"));
+        }
+
+        let text: String = match count_lines(&node.path, self.config.max_line_count) {
+            Ok((count, true)) => format!("{}+ lines", count),
+            Ok((count, false)) => format!("{} lines", count),
+            Err(_) => String::from("unreadable"),
+        };
+        Some(text)
+    }
+
+    /// Whether --mark-binary applies to the node: only regular files, never
+    /// directories or symlinks, have their start read
+    fn is_binary(&self, node: &TreeNode) -> bool {
+        self.config.mark_binary
+            && !node.is_dir
+            && !node.is_symlink
+            && node.path.is_file()
+            && encoding::is_probably_binary(&node.path)
+    }
+
+    /// The --link-count column: hard links of the entry, or of the target of
+    /// a symlink. Always `None` off Unix.
+    fn link_count(&self, node: &TreeNode) -> Option<String> {
+        if !self.config.link_count {
+            return None;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata: fs::Metadata = fs::metadata(&node.path).or_else(|_| fs::symlink_metadata(&node.path)).ok()?;
+            let links: u64 = metadata.nlink();
+            Some(format!("{} {}", links, if links == 1 { "link" } else { "links" }))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = node;
+            None
+        }
+    }
+
+    fn replace_home(&self, path: &Path) -> String {
+        // Only abbreviate paths that actually live under the home directory
+        if let Some(home_dir) = &self.home_dir
+            && let Ok(relative) = path.strip_prefix(home_dir)
+        {
+            if relative.as_os_str().is_empty() {
+                return String::from("~");
+            }
+            return format!("~/{}", relative.display());
+        }
+        path.to_string_lossy().to_string()
+    }
+
+    /// Rewrites the separators of a displayed path to --path-separator
+    fn with_separator(&self, path: String) -> String {
+        match self.config.path_separator {
+            Some(separator) => path
+                .chars()
+                .map(|c: char| if std::path::is_separator(c) { separator } else { c })
+                .collect(),
+            None => path,
+        }
+    }
+
+    fn output_path(&self) -> Option<&PathBuf> {
+        // `-o -` is the conventional spelling for stdout
+        self.config
+            .output
+            .as_ref()
+            .filter(|output_path| output_path.as_os_str() != "-")
+    }
+
+    fn writes_to_file(&self) -> bool {
+        self.output_path().is_some()
+    }
+
+    fn output_result(&self, content: &str) -> TreeResult<()> {
+        let finished: String = self.finish_output(content);
+        let content: &str = &finished;
+        if let Some(output_path) = self.output_path() {
+            // An identical file is left alone so its timestamp stays put
+            let unchanged: bool = self.config.output_if_changed
+                && fs::read(output_path).is_ok_and(|existing: Vec<u8>| existing == content.as_bytes());
+            if unchanged {
+                println!("No changes");
+            } else if let Some(part_size) = self.config.output_split_size {
+                let parts: Vec<PathBuf> = write_parts(output_path, content, part_size)?;
+                println!("Tree output written to {} parts.", parts.len());
+            } else {
+                let mut file: fs::File = fs::File::create(output_path)?;
+                file.write_all(content.as_bytes())?;
+                println!("Tree output generated successfully.");
+            }
+        } else {
+            print!("{}", content);
+        }
+        self.print_error_summary()
+    }
+
+    /// Turns the rendered text into the bytes to write: --line-ending and
+    /// --wrap first, so --limit-output-bytes counts the final bytes. The
+    /// listing is cut inside the fences, which stay complete unless the limit
+    /// is too small for them.
+    fn finish_output(&self, content: &str) -> String {
+        let translated: String = match self.config.line_ending {
+            LineEnding::Lf => content.to_string(),
+            LineEnding::Crlf => content.replace('\n', "\r\n"),
+        };
+        let (open, body, close) = self.wrap_parts(&translated);
+        let fences: usize = open.len() + close.len();
+        match self.config.limit_output_bytes {
+            Some(limit) if fences > limit => self.limit_output(&body, limit),
+            Some(limit) => format!("{}{}{}", open, self.limit_output(&body, limit - fences), close),
+            None => format!("{}{}{}", open, body, close),
+        }
+    }
+
+    fn newline(&self) -> &'static str {
+        match self.config.line_ending {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    /// Applies --limit-output-bytes to `content`, which may use `budget`
+    /// bytes of the limit: longer content is cut at the last complete line
+    /// that leaves room for the truncation notice. The notice gets shorter,
+    /// down to nothing, when the budget is too small for it.
+    fn limit_output(&self, content: &str, budget: usize) -> String {
+        if content.len() <= budget {
+            return content.to_string();
+        }
+
+        let limit: usize = self.config.limit_output_bytes.unwrap_or(budget);
+        let notice: String = [
+            format!("[output truncated at {} bytes]{}", limit, self.newline()),
+            format!("[truncated]{}", self.newline()),
+        ]
+        .into_iter()
+        .find(|notice: &String| notice.len() <= budget)
+        .unwrap_or_default();
+        let available: usize = budget - notice.len();
+        let end: usize = content.as_bytes()[..available]
+            .iter()
+            .rposition(|&byte: &u8| byte == b'\n')
+            .map_or(0, |newline: usize| newline + 1);
+        format!("{}{}", &content[..end], notice)
+    }
+
+    /// The opening fence, the content as it goes inside and the closing
+    /// fence of the --wrap block
+    fn wrap_parts(&self, content: &str) -> (String, String, String) {
+        match self.config.wrap {
+            Wrap::None => (String::new(), content.to_string(), String::new()),
+            Wrap::Markdown => (
+                format!("```{}", self.newline()),
+                content.to_string(),
+                format!("```{}", self.newline()),
+            ),
+            Wrap::Html => (
+                format!("<pre>{}", self.newline()),
+                content.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+                format!("</pre>{}", self.newline()),
+            ),
+        }
+    }
+
+    /// Reports the errors skipped by --ignore-errors on stderr. With
+    /// --report-errors-json going to stderr, the JSON replaces the summary.
+    fn print_error_summary(&self) -> TreeResult<()> {
+        let json_to_stderr: bool = match &self.config.report_errors_json {
+            Some(path) if path.as_os_str() == "-" => {
+                eprintln!("{}", self.errors_json());
+                true
+            }
+            Some(path) => {
+                fs::write(path, format!("{}\n", self.errors_json()))?;
+                false
+            }
+            None => false,
+        };
+
+        if self.errors.is_empty() || json_to_stderr {
+            return Ok(());
+        }
+
+        eprintln!("{} errors encountered", self.errors.len());
+        if self.config.verbose_errors {
+            for error in &self.errors {
+                eprintln!("  {}: {}", error.path.display(), error.message);
+            }
+        }
+        Ok(())
+    }
+
+    fn errors_json(&self) -> String {
+        self.errors_value().to_json(!self.config.json_compact)
+    }
+
+    fn errors_value(&self) -> JsonValue {
+        let errors: Vec<JsonValue> = self
+            .errors
+            .iter()
+            .map(|error: &EntryError| {
+                JsonValue::object(vec![
+                    ("path", JsonValue::string(error.path.to_string_lossy())),
+                    ("message", JsonValue::string(error.message.as_str())),
+                ])
+            })
+            .collect();
+        JsonValue::Array(errors)
+    }
+}
+
+/// The --size-by-ext totals, largest first and ties by extension
+fn sorted_ext_bytes(stats: &FileStats) -> Vec<(&str, u64)> {
+    let mut totals: Vec<(&str, u64)> = stats.ext_bytes.iter().map(|(ext, &bytes)| (ext.as_str(), bytes)).collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    totals
+}
+
+/// Counts the newlines of a file like `wc -l`, stopping once there are more
+/// than `limit`; the flag tells whether counting stopped early
+fn count_lines(path: &Path, limit: Option<usize>) -> std::io::Result<(usize, bool)> {
+    let mut file: fs::File = fs::File::open(path)?;
+    let mut buffer: Vec<u8> = vec![0; 64 * 1024];
+    let mut count: usize = 0;
+
+    loop {
+        let read: usize = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok((count, false));
+        }
+
+        count += buffer[..read].iter().filter(|&&byte: &&u8| byte == b'\n').count();
+        if let Some(limit) = limit
+            && count > limit
+        {
+            return Ok((limit, true));
+        }
+    }
+}
+
+/// Whether a directory lives on another device than its parent
+#[cfg(unix)]
+fn is_mount_point(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let Some(parent) = path.parent() else {
+        return false;
+    };
+    let parent: &Path = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+    match (fs::symlink_metadata(path), fs::metadata(parent)) {
+        (Ok(metadata), Ok(parent_metadata)) => metadata.dev() != parent_metadata.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_mount_point(_path: &Path) -> bool {
+    false
+}
+
+fn compile_patterns(patterns: &[String], config: &Config, ignore_case: bool) -> Patterns {
+    // Config::parse_args has already rejected invalid patterns
+    Patterns::new(patterns, config.pattern_syntax, ignore_case).unwrap_or(Patterns::Glob {
+        globs: Vec::new(),
+        ignore_case,
+    })
+}
+
+/// Whether the filesystem holding `root` treats names that differ only in
+/// case as the same, probed by looking up one of the root's entries (or the
+/// root itself) with the case of its name swapped
+fn is_case_insensitive_fs(root: &Path) -> bool {
+    let Ok(root) = root.canonicalize() else {
+        return false;
+    };
+    let entries = fs::read_dir(&root)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry: fs::DirEntry| entry.path());
+    entries
+        .chain(std::iter::once(root.clone()))
+        .find_map(|path: PathBuf| {
+            let name: String = path.file_name()?.to_string_lossy().to_string();
+            let swapped: String = name
+                .chars()
+                .map(|c: char| if c.is_ascii_lowercase() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() })
+                .collect();
+            (swapped != name).then(|| is_same_entry(&path, &path.with_file_name(swapped)))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn is_same_entry(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_entry(_a: &Path, b: &Path) -> bool {
+    fs::symlink_metadata(b).is_ok()
+}
+
+/// Whether an entry is a directory, following symlinks. The type comes with
+/// the directory listing on most systems, so only symlinks cost a `stat`.
+fn entry_is_dir(entry: &fs::DirEntry) -> bool {
+    match entry.file_type() {
+        Ok(file_type) if !file_type.is_symlink() => file_type.is_dir(),
+        _ => entry.path().is_dir(),
+    }
+}
+
+/// Writes `content` to numbered files next to `output_path`, each holding
+/// whole lines up to `part_size` bytes; a longer line gets a part of its own
+fn write_parts(output_path: &Path, content: &str, part_size: usize) -> TreeResult<Vec<PathBuf>> {
+    let stem: String = output_path
+        .file_stem()
+        .map_or_else(|| "tree".to_string(), |stem| stem.to_string_lossy().to_string());
+    let extension: String = output_path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut chunks: Vec<&str> = Vec::new();
+    let mut start: usize = 0;
+    let mut end: usize = 0;
+    for line in content.split_inclusive('\n') {
+        if end > start && end - start + line.len() > part_size {
+            chunks.push(&content[start..end]);
+            start = end;
+        }
+        end += line.len();
+    }
+    if end > start || chunks.is_empty() {
+        chunks.push(&content[start..end]);
+    }
+
+    let mut parts: Vec<PathBuf> = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let part_path: PathBuf = output_path.with_file_name(format!("{}.{:03}{}", stem, index + 1, extension));
+        fs::write(&part_path, chunk)?;
+        parts.push(part_path);
+    }
+    Ok(parts)
+}
+
+/// The longest directory every path lies in, or `None` if they share none.
+/// Only the parents are compared, so no path is trimmed down to nothing.
+fn common_dir_prefix<'p>(mut paths: impl Iterator<Item = &'p Path>) -> Option<PathBuf> {
+    let mut prefix: Vec<Component> = paths.next()?.parent()?.components().collect();
+    for path in paths {
+        let parent: Vec<Component> = path.parent().map_or_else(Vec::new, |parent: &Path| parent.components().collect());
+        let shared: usize = prefix.iter().zip(&parent).take_while(|(a, b)| a == b).count();
+        prefix.truncate(shared);
+    }
+
+    let prefix: PathBuf = prefix.iter().collect();
+    (!prefix.as_os_str().is_empty()).then_some(prefix)
+}
+
+/// Advances a SplitMix64 generator, which is plenty for --shuffle
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z: u64 = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// The bytes of a path as the OS stores them; lossy off Unix
+fn path_bytes(path: &Path) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        path.to_string_lossy().into_owned().into_bytes()
+    }
+}
+
+/// Width available to --columns, --compact-files and --info: `COLUMNS` if set, else
+/// the width of the terminal the tree is printed to, like `ls`
+fn terminal_width(config: &Config) -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns: String| columns.trim().parse::<usize>().ok())
+        .filter(|&columns: &usize| columns > 0)
+        .or_else(|| {
+            let to_terminal: bool =
+                config.output.is_none() && config.output_dir.is_none() && std::io::stdout().is_terminal();
+            to_terminal.then(terminal::size).flatten().map(|(_, columns)| columns)
+        })
+        .filter(|&columns: &usize| columns > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// What `--watch` polls: every listed entry, whose own changes show in the
+/// tree, and the files that decide what is listed. Directories cover entries
+/// that appear or vanish, since that changes their modification time.
+fn watched_paths(config: &Config, roots: &[(TreeNode, FileStats)]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for (root, _) in roots {
+        for (_, node) in root {
+            paths.push(node.path.clone());
+            if node.is_dir && config.gitignore {
+                paths.push(node.path.join(".gitignore"));
+            }
+        }
+        if config.info {
+            paths.push(root.path.join(info::INFO_FILE));
+        }
+    }
+    paths
+}
+
+/// Names the `--output-dir` file of a root after its last path component,
+/// resolving `.` and `..` to the directory they stand for
+fn root_file_stem(path: &Path) -> String {
+    let resolved: PathBuf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    resolved
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("root"))
+}
+
+/// What the --sort keys of an entry compare
+struct SortValues {
+    name: String,
+    extension: String,
+    modified: Option<SystemTime>,
+    size: u64,
+}
+
+/// A failure skipped by --ignore-errors
+struct EntryError {
+    path: PathBuf,
+    message: String,
+    /// Shown after the entry's name in the tree
+    marker: &'static str,
+}
+
+/// A directory whose entries are still being walked by `visit_dir`
+struct DirFrame {
+    entries: std::vec::IntoIter<fs::DirEntry>,
+    level: usize,
+    /// Where the directory really lives, to detect symlink loops
+    canonical: Option<PathBuf>,
+    /// Directory symlinks followed from the root down to here
+    symlink_hops: usize,
+    nodes: Vec<TreeNode>,
+    /// The directory's own node, completed once its entries are walked;
+    /// `None` for the root
+    pending: Option<TreeNode>,
+}
+
+/// A list of sibling nodes being printed by `render_children`
+struct RenderFrame<'n> {
+    nodes: &'n [TreeNode],
+    /// The display names of `nodes` lined up by --align-names, empty without it
+    names: Vec<String>,
+    index: usize,
+    prefix: String,
+    level: usize,
+    /// Entries left out after the last node
+    omitted: Omitted,
+}
+
+/// Entries left out of a directory's listing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Omitted {
+    /// Direct entries dropped by --max-entries-total
+    entries: usize,
+    /// Files beyond --max-files-per-dir
+    files: usize,
+}
+
+impl Omitted {
+    fn is_empty(&self) -> bool {
+        self.entries == 0 && self.files == 0
+    }
+
+    fn add(self, other: Omitted) -> Omitted {
+        Omitted {
+            entries: self.entries + other.entries,
+            files: self.files + other.files,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn config(args: &[&str]) -> Config {
+        let mut argv: Vec<&str> = vec!["tree", "--color", "never"];
+        argv.extend_from_slice(args);
+        Config::parse_args_from(argv)
+    }
+
+    /// The output of a run over `args`
+    fn tree(args: &[&str]) -> String {
+        let config: Config = config(args);
+        TreeGenerator::new(&config).generate_output().unwrap()
+    }
+
+    /// A chain of `depth` directories named `d` under `root`, ending in a file
+    fn deep_chain(root: &Path, depth: usize) -> TreeNode {
+        let paths: Vec<PathBuf> = (1..=depth)
+            .scan(root.to_path_buf(), |path: &mut PathBuf, _| {
+                path.push("d");
+                Some(path.clone())
+            })
+            .collect();
+        let mut node: TreeNode = TreeNode::new(String::from("leaf"), paths[depth - 1].join("leaf"), false);
+        for path in paths.into_iter().rev() {
+            let mut parent: TreeNode = TreeNode::new(String::from("d"), path, true);
+            parent.children.push(node);
+            node = parent;
+        }
+        let mut tree: TreeNode = TreeNode::new(root.display().to_string(), root.to_path_buf(), true);
+        tree.children.push(node);
+        tree
+    }
+
+    #[test]
+    fn renders_very_deep_trees_without_recursion() {
+        const DEPTH: usize = 3_000;
+        let root: PathBuf = PathBuf::from("/nonexistent-deep-root");
+        let config: Config = config(&["--charset", "ascii", "--noreport", root.to_str().unwrap()]);
+        let generator: TreeGenerator = TreeGenerator::new(&config);
+        let roots: Vec<(TreeNode, FileStats)> = vec![(deep_chain(&root, DEPTH), FileStats::new())];
+
+        let text: String = generator.render_text(&roots).unwrap();
+        assert_eq!(text.lines().count(), DEPTH + 2);
+        assert!(text.lines().last().unwrap().ends_with("`-- leaf"));
+
+        let json: String = generator.render_json(&roots);
+        assert_eq!(json.matches("\"type\": \"directory\"").count(), DEPTH + 1);
+        assert_eq!(json.matches("\"name\": \"leaf\"").count(), 1);
+
+        let copy: TreeNode = roots[0].0.clone();
+        assert_eq!(copy.iter().count(), DEPTH + 2);
+
+        let mut collapsed: TreeNode = copy;
+        collapsed.collapse_chains(None);
+        assert_eq!(collapsed.children.len(), 1);
+        assert_eq!(collapsed.children[0].name.matches('d').count(), DEPTH);
+
+        let mut primary: TreeNode = roots[0].0.clone();
+        diff::merge(&mut primary, roots[0].0.clone(), false, &|_: &mut Vec<TreeNode>| {});
+        assert_eq!(primary.iter().count(), DEPTH + 2);
+        assert!(primary.iter().all(|(_, node)| node.diff.is_none()));
+    }
+
+    #[test]
+    fn deterministic_hides_absolute_paths_in_every_mode() {
+        let dir: TempDir = TempDir::new("deterministic");
+        dir.file("proj/src/main.rs");
+        dir.file("proj/README");
+        let root: String = dir.join("proj").display().to_string();
+        let parent: String = dir.path().display().to_string();
+
+        let grouped: String = tree(&["--deterministic", "--group-by-ext", &root]);
+        assert!(!grouped.contains(&parent), "{}", grouped);
+        assert!(grouped.contains("proj/src/main.rs\n"), "{}", grouped);
+
+        let paths: String = tree(&["--deterministic", "--paths-only", &root]);
+        assert!(!paths.contains(&parent), "{}", paths);
+        assert_eq!(paths, "proj/README\nproj/src/\nproj/src/main.rs\n");
+
+        let listing: String = tree(&["--deterministic", "--noreport", &root]);
+        assert!(listing.starts_with("proj/\n"), "{}", listing);
+
+        // Without --deterministic the paths are kept as given
+        let paths: String = tree(&["--paths-only", &root]);
+        assert!(paths.contains(&format!("{}/README", root)), "{}", paths);
+    }
+
+    #[test]
+    fn diff_marks_follow_each_tree_not_absolute_paths() {
+        let dir: TempDir = TempDir::new("diff");
+        dir.file("a/x");
+        dir.file("a/m");
+        dir.file("b");
+        let primary: String = dir.join("a").display().to_string();
+        let other: String = dir.path().display().to_string();
+
+        let output: String = tree(&["--charset", "ascii", "--diff", &other, &primary]);
+        let listing: Vec<&str> = output.lines().skip(1).collect();
+        assert_eq!(
+            listing,
+            [
+                "|-- [-] a/",
+                "|   |-- [-] m",
+                "|   `-- [-] x",
+                "|-- [-] b",
+                "|-- [+] m",
+                "`-- [+] x",
+                "",
+                "0 directories, 2 files, 2 added, 4 removed",
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_merges_children_in_sort_order() {
+        let dir: TempDir = TempDir::new("diff-sort");
+        dir.file("new/b");
+        dir.file("new/d");
+        dir.file("old/a");
+        dir.file("old/c");
+        let primary: String = dir.join("new").display().to_string();
+        let other: String = dir.join("old").display().to_string();
+
+        let output: String = tree(&["--charset", "ascii", "--noreport", "--sort", "name-", "--diff", &other, &primary]);
+        let names: Vec<&str> = output.lines().skip(1).map(|line: &str| &line[line.len() - 1..]).collect();
+        assert_eq!(names, ["d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn diff_keeps_removed_entries_out_of_duplicates() {
+        let dir: TempDir = TempDir::new("diff-duplicates");
+        dir.file("new/x/same");
+        dir.file("old/y/same");
+        let primary: String = dir.join("new").display().to_string();
+        let other: String = dir.join("old").display().to_string();
+
+        let output: String = tree(&["--charset", "ascii", "--duplicate-names", "--diff", &other, &primary]);
+        assert!(!output.contains("[dup"), "{}", output);
+        assert!(output.ends_with("0 duplicate names\n"), "{}", output);
+    }
+
+    /// What --limit-output-bytes and --wrap make of `content`
+    fn finished(args: &[&str], content: &str) -> String {
+        let config: Config = config(args);
+        TreeGenerator::new(&config).finish_output(content)
+    }
+
+    #[test]
+    fn limit_output_never_exceeds_the_limit() {
+        let content: String = "abcd\n".repeat(10);
+        assert_eq!(finished(&["--limit-output-bytes", "50"], &content), content);
+        assert_eq!(
+            finished(&["--limit-output-bytes", "40"], &content),
+            "abcd\n[output truncated at 40 bytes]\n"
+        );
+        // Too small for the full notice, then for any notice at all
+        assert_eq!(finished(&["--limit-output-bytes", "16"], &content), "[truncated]\n");
+        assert_eq!(finished(&["--limit-output-bytes", "5"], &content), "abcd\n");
+        assert_eq!(finished(&["--limit-output-bytes", "4"], &content), "");
+    }
+
+    #[test]
+    fn limit_output_counts_the_wrap_fences() {
+        let content: String = "abcd\n".repeat(10);
+        let wrapped: String = finished(&["--wrap", "markdown", "--limit-output-bytes", "30"], &content);
+        assert_eq!(wrapped, "```\nabcd\nabcd\n[truncated]\n```\n");
+
+        let escaped: String = finished(&["--wrap", "html", "--limit-output-bytes", "40"], "<a>\n<b>\n<c>\n");
+        assert_eq!(escaped, "<pre>\n&lt;a&gt;\n[truncated]\n</pre>\n");
+    }
+
+    #[test]
+    fn count_lines_only_overflows_past_the_limit() {
+        let dir: TempDir = TempDir::new("lines");
+        let exact: PathBuf = dir.write("exact", "a\nb\nc\n");
+        let longer: PathBuf = dir.write("longer", "a\nb\nc\nd\n");
+
+        assert_eq!(count_lines(&exact, Some(3)).unwrap(), (3, false));
+        assert_eq!(count_lines(&longer, Some(3)).unwrap(), (3, true));
+        assert_eq!(count_lines(&longer, None).unwrap(), (4, false));
+    }
+
+    #[test]
+    fn prune_drops_cut_off_directories_without_files_below() {
+        let dir: TempDir = TempDir::new("prune");
+        dir.dir("e/f");
+        dir.file("g/h/x");
+        dir.file("top");
+        let root: String = dir.path().display().to_string();
+
+        let output: String = tree(&["--charset", "ascii", "--noreport", "--prune", "-L", "0", &root]);
+        assert_eq!(output.lines().skip(1).collect::<Vec<&str>>(), ["|-- g/", "`-- top"]);
+
+        let output: String = tree(&["--charset", "ascii", "--noreport", "--prune", "-L", "1", &root]);
+        assert_eq!(
+            output.lines().skip(1).collect::<Vec<&str>>(),
+            ["|-- g/", "|   `-- h/", "`-- top"]
+        );
+    }
+
+    #[test]
+    fn prune_probe_keeps_directories_it_gives_up_on() {
+        let dir: TempDir = TempDir::new("prune-probe");
+        dir.dir("e/a");
+        dir.dir("e/b");
+        dir.dir("e/c");
+        let root: String = dir.path().display().to_string();
+
+        let unbounded: Config = config(&["--prune", "-L", "1", &root]);
+        assert!(!TreeGenerator::new(&unbounded).has_visible_files(&dir.join("e"), dir.path()));
+        let bounded: Config = config(&["--prune", "-L", "1", "--max-entries-total", "2", &root]);
+        assert!(TreeGenerator::new(&bounded).has_visible_files(&dir.join("e"), dir.path()));
+    }
+
+    /// A directory and a file, each with a symlink to it
+    #[cfg(unix)]
+    fn symlink_fixture() -> TempDir {
+        let dir: TempDir = TempDir::new("symlinks");
+        dir.file("real/inner");
+        dir.write("file", "hello\n");
+        dir.symlink("real", "link_dir");
+        dir.symlink("file", "link_file");
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn no_dereference_report_counts_symlinks_as_links() {
+        let dir: TempDir = symlink_fixture();
+        let root: String = dir.path().display().to_string();
+
+        let output: String = tree(&[&root]);
+        assert!(output.ends_with("\n2 directories, 4 files\n"), "{}", output);
+
+        let output: String = tree(&["--no-dereference-report", &root]);
+        assert!(output.ends_with("\n1 directories, 3 files, 2 links\n"), "{}", output);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn no_dereference_files_sizes_the_links_themselves() {
+        let dir: TempDir = symlink_fixture();
+        let root: String = dir.path().display().to_string();
+        let size_of = |output: &str, name: &str| -> String {
+            let line: &str = output.lines().find(|line: &&str| line.ends_with(name)).unwrap();
+            line[line.find('[').unwrap() + 1..line.find(']').unwrap()].trim().to_string()
+        };
+
+        // The target holds 6 bytes, the link the 4 bytes of `file`
+        let output: String = tree(&["-s", &root]);
+        assert_eq!(size_of(&output, " link_file"), "6");
+        assert_eq!(size_of(&output, " file"), "6");
+
+        let output: String = tree(&["-s", "--no-dereference-files", &root]);
+        assert_eq!(size_of(&output, " link_file"), "4");
+        assert_eq!(size_of(&output, " file"), "6");
+        // Directory symlinks are still followed
+        assert!(output.contains("link_dir/\n"), "{}", output);
+        assert_eq!(output.matches(" inner\n").count(), 2, "{}", output);
+    }
+
+    /// `a/back` links to the root, so following it would never end
+    #[cfg(unix)]
+    fn loop_fixture() -> TempDir {
+        let dir: TempDir = TempDir::new("loop");
+        dir.file("a/file");
+        dir.symlink("..", "a/back");
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop_policy_mark_lists_the_link_unfollowed() {
+        let dir: TempDir = loop_fixture();
+        let root: String = dir.path().display().to_string();
+
+        let output: String = tree(&["--charset", "ascii", "--noreport", "--symlink-loop-policy", "mark", &root]);
+        assert_eq!(
+            output.lines().skip(1).collect::<Vec<&str>>(),
+            ["`-- a/", "    |-- back/ [recursive]", "    `-- file"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop_policy_skip_leaves_the_link_out() {
+        let dir: TempDir = loop_fixture();
+        let root: String = dir.path().display().to_string();
+
+        let output: String = tree(&["--charset", "ascii", "--noreport", "--symlink-loop-policy", "skip", &root]);
+        assert_eq!(output.lines().skip(1).collect::<Vec<&str>>(), ["`-- a/", "    `-- file"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop_policy_error_aborts() {
+        let dir: TempDir = loop_fixture();
+        let root: String = dir.path().display().to_string();
+
+        let config: Config = config(&["--symlink-loop-policy", "error", &root]);
+        let result: TreeResult<String> = TreeGenerator::new(&config).generate_output();
+        match result {
+            Err(TreeError::SymlinkLoop(path)) => assert_eq!(path, dir.join("a/back")),
+            other => panic!("expected a symlink loop error, got {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn max_symlink_depth_stops_chains_that_never_loop() {
+        // a/b -> ../b, b/c -> ../c: following a/b/c takes two symlinks
+        let dir: TempDir = TempDir::new("symlink-depth");
+        dir.dir("a");
+        dir.dir("b");
+        dir.file("c/file");
+        dir.symlink("../b", "a/b");
+        dir.symlink("../c", "b/c");
+        let root: String = dir.join("a").display().to_string();
+
+        let output: String = tree(&["--charset", "ascii", "--noreport", &root]);
+        assert_eq!(
+            output.lines().skip(1).collect::<Vec<&str>>(),
+            ["`-- b/", "    `-- c/", "        `-- file"]
+        );
+
+        let output: String = tree(&["--charset", "ascii", "--noreport", "--max-symlink-depth", "1", &root]);
+        assert_eq!(
+            output.lines().skip(1).collect::<Vec<&str>>(),
+            ["`-- b/", "    `-- c/ [symlink depth]"]
+        );
+
+        let output: String = tree(&["--charset", "ascii", "--noreport", "--max-symlink-depth", "0", &root]);
+        assert_eq!(output.lines().skip(1).collect::<Vec<&str>>(), ["`-- b/ [symlink depth]"]);
+    }
+
+    /// The entry lines of a run over `args`, without the root and the report
+    fn listing(args: &[&str]) -> Vec<String> {
+        let mut argv: Vec<&str> = vec!["--charset", "ascii", "--noreport"];
+        argv.extend_from_slice(args);
+        tree(&argv).lines().skip(1).map(String::from).collect()
+    }
+
+    #[test]
+    fn all_lists_dot_names_but_never_pseudo_entries() {
+        let dir: TempDir = TempDir::new("dots");
+        dir.file(".hidden");
+        dir.file("..double");
+        dir.file("...");
+        dir.file("plain");
+        let root: String = dir.path().display().to_string();
+
+        assert_eq!(listing(&["-a", &root]), ["|-- ...", "|-- ..double", "|-- .hidden", "`-- plain"]);
+        assert_eq!(listing(&[&root]), ["`-- plain"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_filters_look_at_the_links_themselves() {
+        let dir: TempDir = symlink_fixture();
+        let root: String = dir.path().display().to_string();
+
+        assert_eq!(listing(&["--exclude-symlinks", &root]), ["|-- file", "`-- real/", "    `-- inner"]);
+        // Real directories are still traversed, but hold no symlinks here
+        assert_eq!(listing(&["--only-symlinks", &root]), ["|-- link_dir/", "|-- link_file", "`-- real/"]);
+    }
+
+    #[test]
+    fn ignore_hidden_dirs_keeps_dotfiles() {
+        let dir: TempDir = TempDir::new("hidden-dirs");
+        dir.file(".env");
+        dir.file(".cache/data");
+        dir.file("src/.keep");
+        dir.file("src/main.rs");
+        let root: String = dir.path().display().to_string();
+
+        assert_eq!(
+            listing(&["--ignore-hidden-dirs", &root]),
+            ["|-- .env", "`-- src/", "    |-- .keep", "    `-- main.rs"]
+        );
+        assert_eq!(
+            listing(&["--ignore-hidden-dirs", "-a", &root]),
+            ["|-- .cache/", "|   `-- data", "|-- .env", "`-- src/", "    |-- .keep", "    `-- main.rs"]
+        );
+        assert_eq!(listing(&[&root]), ["`-- src/", "    `-- main.rs"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn du_counts_allocated_blocks_unless_apparent_size() {
+        use std::os::unix::fs::MetadataExt;
+        let dir: TempDir = TempDir::new("apparent");
+        let small: PathBuf = dir.write("small", "x");
+        // A sparse file is longer than the blocks it occupies
+        let sparse: PathBuf = dir.file("sparse");
+        fs::File::options().write(true).open(&sparse).unwrap().set_len(1 << 20).unwrap();
+
+        let allocated: Config = config(&["--du"]);
+        let allocated: TreeGenerator = TreeGenerator::new(&allocated);
+        let apparent: Config = config(&["--du", "--apparent-size"]);
+        let apparent: TreeGenerator = TreeGenerator::new(&apparent);
+        for path in [&small, &sparse] {
+            let metadata: fs::Metadata = fs::metadata(path).unwrap();
+            assert_eq!(allocated.entry_size(path).unwrap(), metadata.blocks() * 512);
+            assert_eq!(apparent.entry_size(path).unwrap(), metadata.len());
+        }
+        assert_eq!(apparent.entry_size(&small).unwrap(), 1);
+        assert_eq!(apparent.entry_size(&sparse).unwrap(), 1 << 20);
+
+        // Without --du sizes are always lengths
+        let listing: Config = config(&["-s"]);
+        assert_eq!(TreeGenerator::new(&listing).entry_size(&sparse).unwrap(), 1 << 20);
+    }
+
+    #[test]
+    fn ignore_priority_decides_between_gitignore_and_excludes() {
+        let dir: TempDir = TempDir::new("ignore-priority");
+        dir.dir(".git");
+        dir.write(".gitignore", "*.log\n!keep.log\n");
+        dir.file("keep.log");
+        dir.file("other.log");
+        dir.file("main.rs");
+        let root: String = dir.path().display().to_string();
+
+        assert_eq!(listing(&["-g", &root]), ["|-- keep.log", "`-- main.rs"]);
+        assert_eq!(listing(&["-g", "-I", "keep.log", &root]), ["`-- main.rs"]);
+        assert_eq!(
+            listing(&["-g", "-I", "keep.log", "--ignore-priority", "gitignore", &root]),
+            ["|-- keep.log", "`-- main.rs"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn plain_directories_are_not_mount_points() {
+        let dir: TempDir = TempDir::new("mounts");
+        let sub: PathBuf = dir.dir("sub");
+        assert!(!is_mount_point(&sub));
+        assert!(!is_mount_point(&dir.join("missing")));
+        // The filesystem root has no parent to compare with
+        assert!(!is_mount_point(Path::new("/")));
+
+        let root: String = dir.path().display().to_string();
+        assert_eq!(listing(&["--mark-mounts", &root]), ["`-- sub/"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn proc_is_a_mount_point() {
+        let mounts: String = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+        if !mounts.lines().any(|line: &str| line.split_whitespace().nth(1) == Some("/proc")) {
+            return;
+        }
+        assert!(is_mount_point(Path::new("/proc")));
+    }
+
+    /// Runs over a real mount point named by `TREE_TEST_MOUNT`, e.g. a
+    /// loop-mounted image, since creating one needs privileges
+    #[cfg(unix)]
+    #[test]
+    fn mark_mounts_marks_a_mounted_directory() {
+        let Some(mount) = std::env::var_os("TREE_TEST_MOUNT").map(PathBuf::from) else {
+            return;
+        };
+        let parent: String = mount.parent().expect("a mount point has a parent").display().to_string();
+        let name: String = mount.file_name().unwrap().to_string_lossy().to_string();
+
+        let output: Vec<String> = listing(&["--mark-mounts", "-d", "-L", "1", &parent]);
+        assert!(output.iter().any(|line: &String| line.ends_with(&format!("{}/ [mount]", name))), "{:?}", output);
+    }
+
+    /// A symlink to itself has a type but no readable target, while a
+    /// dangling one is listed like any other link
+    #[cfg(unix)]
+    #[test]
+    fn ignore_errors_records_unreadable_entries() {
+        let dir: TempDir = TempDir::new("entry-errors");
+        dir.file("file");
+        dir.symlink("dangling-target", "dangling");
+        dir.symlink("self", "self");
+        let root: String = dir.path().display().to_string();
+
+        let config: Config = config(&["--charset", "ascii", "--noreport", "--ignore-errors", "-s", &root]);
+        let mut generator: TreeGenerator = TreeGenerator::new(&config);
+        let output: String = generator.generate_output().unwrap();
+        let lines: Vec<&str> = output.lines().skip(1).collect();
+        assert!(lines[2].ends_with("self [error reading entry]"), "{:?}", lines);
+        assert!(!lines[0].contains("[error"), "{:?}", lines);
+        assert_eq!(generator.errors.len(), 1);
+        assert_eq!(generator.errors[0].path, dir.join("self"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn type_filters_list_or_leave_out_special_files() {
+        let dir: TempDir = TempDir::new("special");
+        dir.file("file");
+        let _listener = std::os::unix::net::UnixListener::bind(dir.join("socket")).unwrap();
+        let root: String = dir.path().display().to_string();
+
+        assert_eq!(listing(&["--sockets", &root]), ["`-- socket="]);
+        assert_eq!(listing(&["--fifos", &root]), Vec::<String>::new());
+        assert_eq!(listing(&["--exclude-sockets", &root]), ["`-- file"]);
+        assert_eq!(listing(&["--exclude-fifos", "--exclude-devices", &root]), ["|-- file", "`-- socket="]);
+    }
+
+    #[test]
+    fn no_recurse_into_follows_fs_case_and_pattern_syntax() {
+        let dir: TempDir = TempDir::new("no-recurse");
+        dir.file("Target/out");
+        dir.file("src/main.rs");
+        let root: String = dir.path().display().to_string();
+
+        let stopped: [&str; 2] = ["|-- Target/ [...]", "`-- src/"];
+        let args = |extra: &[&str]| -> Vec<String> {
+            let mut argv: Vec<&str> = vec!["-d"];
+            argv.extend_from_slice(extra);
+            argv.push(&root);
+            listing(&argv)
+        };
+        assert_eq!(args(&["--fs-case", "insensitive", "--no-recurse-into", "target"]), stopped);
+        assert_eq!(args(&["--fs-case", "sensitive", "--no-recurse-into", "target"]), ["|-- Target/", "`-- src/"]);
+        assert_eq!(args(&["--pattern-syntax", "regex", "--no-recurse-into", "^T.*t$"]), stopped);
+    }
+
+    #[test]
+    fn list_children_applies_each_roots_depth_limit() {
+        let dir: TempDir = TempDir::new("browse-depth");
+        dir.file("a/sub/file");
+        dir.file("b/sub/file");
+        let (a, b): (PathBuf, PathBuf) = (dir.join("a"), dir.join("b"));
+
+        let config: Config = config(&["-L", "1,0", a.to_str().unwrap(), b.to_str().unwrap()]);
+        let mut generator: TreeGenerator = TreeGenerator::new(&config);
+        assert!(generator.lists_level(&a, 1));
+        assert!(!generator.lists_level(&b, 1));
+        assert_eq!(generator.list_children(&a, &a.join("sub"), 1).unwrap().len(), 1);
+        assert!(generator.list_children(&b, &b.join("sub"), 1).unwrap().is_empty());
+        // Switching back to the first root restores its limit
+        assert_eq!(generator.list_children(&a, &a.join("sub"), 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn watch_fingerprint_changes_with_listed_entries() {
+        let dir: TempDir = TempDir::new("watch-fingerprint");
+        dir.write("a/file", "one");
+        let root: String = dir.path().display().to_string();
+
+        let config: Config = config(&["--watch", "-o", &dir.join("out").display().to_string(), &root]);
+        let mut generator: TreeGenerator = TreeGenerator::new(&config);
+        generator.generate_output().unwrap();
+        let listed: Vec<Option<(SystemTime, u64)>> = generator.fingerprint();
+        assert_eq!(generator.fingerprint(), listed);
+
+        dir.write("a/file", "three");
+        let written: Vec<Option<(SystemTime, u64)>> = generator.fingerprint();
+        assert_ne!(written, listed);
+        dir.file("a/new");
+        assert_ne!(generator.fingerprint(), written);
     }
 }